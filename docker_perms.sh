@@ -1,5 +0,0 @@
-#!/bin/bash
-sudo groupadd docker
-sudo usermod -aG docker $USER
-sudo gpasswd -a $USER docker
-newgrp docker
@@ -1,43 +1,134 @@
 import logo from './logo.svg';
 import './App.css';
-import React, { useEffect, useState, useContext } from 'react';
+import React, { useEffect, useState, useContext, useRef } from 'react';
 import LaneViewer from './components/LaneViewer.js';
 import NodeEditor from './components/NodeEditor.js';
 import SrcViewer from './components/srcviewer.js';
 import SplitLayout from './components/SplitLayout.js';
 import LoadingModal from './components/LoadingModal.js';
 import ExecutionInstanceList from './components/ExecutionInstanceList.js';
+import SpanList from './components/SpanList.js';
+import ThreadLanes from './components/ThreadLanes.js';
+import SessionControls from './components/SessionControls.js';
 import GraphViewer from './components/graphviewer.js';
 import EventLoader from './components/EventLoader.js';
 import { useRemoteResource } from './util.js';
-import { callRemote } from './util.js';
+import { callRemote, subscribeEvents, apiErrorText } from './util.js';
+import JobProgress from './components/JobProgress.js';
+import CoverageControls from './components/CoverageControls.js';
 
 function App() {
   const [generalInfo, _setGeneralInfo] = useRemoteResource(null, {}, 'general_info');
+  const [traceId, setTraceId] = useState(0);
   const [currentNodeId, setCurrentNodeId] = useState({ id: null, is_raw: false });
   const updateCurrentNode = (newId) => {
     setCurrentNodeId(newId);
   }
-  const [nodesData, setNodeData] = useRemoteResource(null, {}, 'node_data');
-  const [rawNodesData, setRawNodeData] = useRemoteResource(null, {}, 'get_raw_nodes_and_modules', [nodesData]);
+  const [nodesData, setNodeData] = useRemoteResource(null, { trace_id: traceId }, 'node_data', [traceId]);
+  const [rawNodesData, setRawNodeData] = useRemoteResource(null, { trace_id: traceId }, 'get_raw_nodes_and_modules', [nodesData]);
   const [isLoading, setIsLoading] = useState(false);
+  // Status of the job preparing the graph, null when there is none
+  const [job, setJob] = useState(null);
+  const [settingsVersion, setSettingsVersion] = useState(0);
+  // Jobs started here, whose errors are shown here
+  const startedJobs = useRef(new Set());
+  const graphVersions = useRef({});
 
   const [currentFilePath, setCurrentFilePath] = useState("[none selected]");
   const [currentFileLineNum, setCurrentFileLineNum] = useState(1);
+  // Times each line ran, shaded in the source viewer
+  const [heatMap, setHeatMap] = useState(null);
+  // The job measuring the heat map to show
+  const heatMapJob = useRef(null);
+  const [coverageVersion, setCoverageVersion] = useState(0);
+  const [coverageResponse, _setCoverage] = useRemoteResource({ coverage: null },
+    { trace_id: traceId }, 'coverage', [traceId, coverageVersion]);
+  const [showCoverage, setShowCoverage] = useState(true);
 
 
   const updateNodeData = (update_raw_fn) => {
-    callRemote({}, 'get_raw_nodes_and_modules')
+    callRemote({ trace_id: traceId }, 'get_raw_nodes_and_modules')
       .then(resp => resp.json())
       .then(dta => update_raw_fn(dta))
-      .then(dta => callRemote(dta, 'update_raw_nodes_and_modules')
-        .then(_ => callRemote({}, 'node_data').then(resp => resp.json()).then(resp => { console.log(resp); setNodeData(resp) })
+      .then(dta => { dta.trace_id = traceId; return dta })
+      .then(dta => callRemote(dta, 'update_raw_nodes_and_modules'))
+      .then(resp => resp.json())
+      // The graph is reloaded when /events says it changed
+      .then(resp => startedJobs.current.add(resp.job_id))
+      // A bad annotation is shown by callRemote and the graph is unchanged
+      .catch(() => {})
+  }
+  const startCoverage = () => {
+    callRemote({ trace_id: traceId }, 'start_coverage')
+      .then(resp => resp.json())
+      // /events says when it has been measured
+      .then(resp => startedJobs.current.add(resp.job_id))
+      .catch(() => {});
+  }
+  const cancelJob = () => {
+    callRemote({ job_id: job.job_id }, 'cancel_job')
+      .catch(() => {});
+  }
+
+  // Replays the span to count the lines of the function it begins in.
+  // /events says when the job is done.
+  const showHeatMap = (span) => {
+    let request = {
+      trace_id: traceId,
+      range: { start: span.start, end: span.end },
+      function_address: span.start.addr,
+    };
+    callRemote(request, 'function_heat_map')
+      .then(resp => resp.json())
+      .then(resp => {
+        startedJobs.current.add(resp.job_id);
+        heatMapJob.current = resp.job_id;
+      })
+      .catch(() => {});
+  }
+  const loadHeatMap = (jobId) => {
+    callRemote({ trace_id: traceId, job_id: jobId }, 'function_heat_map_result')
+      .then(resp => resp.json())
+      .then(resp => {
+        setHeatMap(resp.map);
+        let lines = Object.keys(resp.map.line_vs_times_executed).map(Number);
+        setCurrentFilePath(resp.map.source_file);
+        setCurrentFileLineNum(lines.length == 0 ? 1 : Math.min(...lines));
+      })
+      .catch(() => {});
+  }
 
-          .then(_ => updateCurrentNode({ id: null, is_raw: false }))
-        )
-      )
+  const reloadNodeData = () => {
+    callRemote({ trace_id: traceId }, 'node_data')
+      .then(resp => resp.json())
+      .then(resp => setNodeData(resp))
+      .then(_ => updateCurrentNode({ id: null, is_raw: false }))
   }
 
+  // Keeps every client of the server in sync
+  useEffect(() => subscribeEvents((event) => {
+    if (event.type == "graph") {
+      let known = graphVersions.current[event.trace_id];
+      graphVersions.current[event.trace_id] = event.version;
+      if (event.trace_id == traceId && known != null && known != event.version) {
+        reloadNodeData();
+      }
+    } else if (event.type == "coverage" && event.trace_id == traceId) {
+      setCoverageVersion(version => version + 1);
+    } else if (event.type == "settings") {
+      setSettingsVersion(event.version);
+    } else if (event.type == "job" && event.trace_id == traceId) {
+      setJob(event.state == "running" ? event : null);
+      if (event.state == "failed" && startedJobs.current.has(event.job_id)) {
+        alert(apiErrorText(event.error));
+      }
+      if (event.state == "done" && event.job_id == heatMapJob.current) {
+        heatMapJob.current = null;
+        loadHeatMap(event.job_id);
+      }
+    }
+  }), [traceId]);
+
   return (
     <div className="App">
       {isLoading && <LoadingModal />}
@@ -48,22 +139,58 @@ function App() {
       {generalInfo &&
         <p className='subtitle'>{generalInfo.recording_dir}</p>
       }
+      {generalInfo && generalInfo.traces.length > 1 &&
+        <div style={{ display: "flex", justifyContent: "center", gap: "1rem" }}>
+          <p>{"Trace:"}</p>
+          <select value={traceId} onChange={(e) => {
+            setTraceId(parseInt(e.target.value));
+            setHeatMap(null);
+            heatMapJob.current = null;
+            updateCurrentNode({ id: null, is_raw: false });
+          }}>
+            {generalInfo.traces.map((trace) => (
+              <option key={trace.id} value={trace.id}>{trace.id}</option>
+            ))}
+          </select>
+        </div>
+      }
+      {job && <JobProgress status={job} onCancel={cancelJob} />}
+      {generalInfo &&
+        <SessionControls
+          traceId={traceId}
+          setIsLoading={setIsLoading}
+          onLoaded={reloadNodeData} />
+      }
+      {generalInfo &&
+        <CoverageControls
+          coverage={coverageResponse.coverage}
+          busy={job != null}
+          onMeasure={startCoverage}
+          showCoverage={showCoverage}
+          setShowCoverage={setShowCoverage} />
+      }
       {
         (generalInfo && nodesData) ? (
           <SplitLayout>
             <SrcViewer
               key={nodesData}
+              traceId={traceId}
               nodesData={nodesData}
               currentFilePath={currentFilePath}
               setCurrentFilePath={setCurrentFilePath}
               currentFileLineNum={currentFileLineNum}
+              heatMap={heatMap}
+              coverage={showCoverage ? coverageResponse.coverage : null}
+              clearHeatMap={() => setHeatMap(null)}
               updateNodeData={updateNodeData}
               setCurrentFileLineNum={setCurrentFileLineNum} />
             <GraphViewer
               key={nodesData}
+              traceId={traceId}
               nodesData={nodesData}
               updateNodeData={updateNodeData}
               rawNodesData={rawNodesData}
+              settingsVersion={settingsVersion}
               setCurrentFilePath={setCurrentFilePath}
               setCurrentFileLineNum={setCurrentFileLineNum}
               updateCurrentNode={updateCurrentNode} />
@@ -71,6 +198,19 @@ function App() {
           :
           (<h3 style={{}}>{"General and node data did not load. Did the backend crash? Retrying..."}</h3>)
       }
+      {generalInfo && nodesData &&
+        <SpanList
+          traceId={traceId}
+          nodesData={nodesData}
+          showHeatMap={showHeatMap} />
+      }
+      {generalInfo && nodesData &&
+        <ThreadLanes
+          traceId={traceId}
+          nodesData={nodesData}
+          generalInfo={generalInfo}
+          updateCurrentNode={updateCurrentNode} />
+      }
       {currentNodeId.id != null && nodesData &&
         <SplitLayout
           default_split={25}
@@ -86,6 +226,7 @@ function App() {
               currentNodeId={currentNodeId} />
           </div>
           {!currentNodeId.is_raw && <ExecutionInstanceList
+            traceId={traceId}
             nodesData={nodesData}
             generalInfo={generalInfo}
             currentNodeId={currentNodeId} />}
@@ -0,0 +1,33 @@
+import React from 'react';
+
+// Measures which source lines the recording ran, for the source
+// viewer to shade
+const CoverageControls = (props) => {
+  let coverage = props.coverage;
+  let summary = null;
+  if (coverage) {
+    let found = 0;
+    let hit = 0;
+    for (const file of coverage.files) {
+      let counts = Object.values(file.lines);
+      found += counts.length;
+      hit += counts.filter(count => count > 0).length;
+    }
+    summary = `${hit} of ${found} measured lines ran`;
+  }
+  return (
+    <div className="coverage-controls">
+      <button onClick={props.onMeasure} disabled={props.busy}>
+        {coverage ? "Measure Coverage Again" : "Measure Coverage"}
+      </button>
+      {summary &&
+        <label>
+          <input type="checkbox" checked={props.showCoverage}
+            onChange={(e) => props.setShowCoverage(e.target.checked)} />
+          {`Shade coverage (${summary})`}
+        </label>}
+    </div>
+  );
+}
+
+export default CoverageControls;
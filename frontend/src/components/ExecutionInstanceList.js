@@ -15,9 +15,9 @@ const ExecutionInstanceList = (props) => {
   let currentNodeId = props.currentNodeId.id;
   //let currentNode = nodesData.nodes[currentNodeId];
 
-  const [instances, _set] = useRemoteResource({ val: [] },
-    { "synoptic_node_id": currentNodeId },
-    'addr_occurrences', [currentNodeId])
+  const [instances, _set] = useRemoteResource({ val: [], captures: [] },
+    { "trace_id": props.traceId, "synoptic_node_id": currentNodeId },
+    'addr_occurrences', [currentNodeId, props.traceId])
 
   // Function to handle hover events on list items
   const handleHover = (item) => {
@@ -56,7 +56,7 @@ const ExecutionInstanceList = (props) => {
     i_lanes.push({ laneId: 'i', label: `Event instances` })
     const hoveredColor = "#ea8080";
     let usedfts = [];
-    let ftmap = generalInfo.traces[0].frame_time_map;
+    let ftmap = generalInfo.traces[props.traceId].frame_time_map;
     for (const instance of instances.val) {
       if (usedfts.includes(instance.frame_time)) {
         continue;
@@ -88,9 +88,10 @@ const ExecutionInstanceList = (props) => {
   const dateFormat = (ms) => new Date(ms).toJSON();
   // Function to handle click events on list items
   const handleClick = (item) => {
-    callRemote({ "start_time": item }, "create_gdb_server")
+    callRemote({ "trace_id": props.traceId, "start_time": item }, "create_gdb_server")
       .then(response => response.json())
-      .then(response => setModalText(response.value));
+      .then(response => setModalText(response.value))
+      .catch(() => {});
   }
 
   return (
@@ -123,12 +124,13 @@ const ExecutionInstanceList = (props) => {
                 <tr>
                   <th className="execution-instance-list__header">Frametime</th>
                   <th className="execution-instance-list__header">Addr instance in frame</th>
+                  <th className="execution-instance-list__header">Captured values</th>
                 </tr>
               </thead>
               <tbody>
                 {
                   // Map over the instances and create a table row for each instance
-                  instances.val.map((instance) => (
+                  instances.val.map((instance, i) => (
                     <tr
                       className="execution-instance-list__row"
                       key={instance.frame_time * 1000000000 + instance.instance_of_addr}
@@ -139,6 +141,9 @@ const ExecutionInstanceList = (props) => {
                     >
                       <td className="execution-instance-list__cell">{instance.frame_time}</td>
                       <td className="execution-instance-list__cell">{instance.instance_of_addr}</td>
+                      <td className="execution-instance-list__cell">
+                        {(instances.captures[i] || []).map(c => `${c.expression} = ${c.value}`).join(', ')}
+                      </td>
                     </tr>
                   ))
                 }
@@ -0,0 +1,22 @@
+import React from 'react';
+
+const phaseNames = {
+  replay: "Replaying",
+  synoptic: "Inferring the graph",
+  layout: "Laying out the graph",
+};
+
+// Shown while a job replays the trace. The previous graph stays
+// usable until it is done.
+const JobProgress = (props) => {
+  let status = props.status;
+  return (
+    <div className="job-progress">
+      <span>{`${phaseNames[status.phase]} ${Math.floor(status.percent)}%`}</span>
+      <progress max="100" value={status.percent} />
+      <button onClick={props.onCancel}>{"Cancel"}</button>
+    </div>
+  );
+}
+
+export default JobProgress;
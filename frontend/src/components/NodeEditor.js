@@ -14,6 +14,8 @@ const NodeEditor = (props) => {
   const [name, setName] = useState(props.mode == 'add' ? props.name : node.name);
   const [type, setType] = useState(props.mode == 'add' ? 'Event' : node.node_type);
   const [lineLocation, setLineLocation] = useState(props.mode == 'add' ? props.line : node.location.line_num);
+  const [captures, setCaptures] = useState(props.mode == 'add' ? '' : (node.captures || []).join(', '));
+  const [when, setWhen] = useState(props.mode == 'add' ? '' : (node.when || ''));
 
   const handleNameChange = (event) => {
     setName(event.target.value);
@@ -27,6 +29,17 @@ const NodeEditor = (props) => {
     setLineLocation(event.target.value);
   }
 
+  const handleCapturesChange = (event) => {
+    setCaptures(event.target.value);
+  }
+
+  const handleWhenChange = (event) => {
+    setWhen(event.target.value);
+  }
+
+  // "a, ptr->field" -> ["a", "ptr->field"]
+  const parsedCaptures = () => captures.split(',').map(c => c.trim()).filter(c => c.length > 0);
+
   const handleAddNode = () => {
     let update_raw_fn = (raw_n_data) => {
       console.log(raw_n_data);
@@ -43,6 +56,8 @@ const NodeEditor = (props) => {
         },
         labeled_transitions: [],
         node_attributes: {},
+        captures: parsedCaptures(),
+        when: when.trim() === '' ? null : when.trim(),
       };
       raw_n_data.nodes[0] = (new_node);
       raw_n_data.rerun_level = 0;
@@ -64,6 +79,8 @@ const NodeEditor = (props) => {
       raw_n_data.nodes[addr].module = selectedModule;
       raw_n_data.nodes[addr].node_type = type;
       raw_n_data.nodes[addr].location.line_num = parseInt(lineLocation);
+      raw_n_data.nodes[addr].captures = parsedCaptures();
+      raw_n_data.nodes[addr].when = when.trim() === '' ? null : when.trim();
       raw_n_data.rerun_level = 0;
       return raw_n_data;
     }
@@ -114,12 +131,24 @@ const NodeEditor = (props) => {
         <select className="node-editor__input" value={type} onChange={handleTypeChange}>
           <option value="Event">Event</option>
           <option value="Flow">Flow</option>
+          <option value="SpanBegin">Span begin</option>
+          <option value="SpanEnd">Span end</option>
+          <option value="FunctionEntry">Function entry</option>
+          <option value="FunctionExit">Function exit</option>
         </select>
       </label>
       <label className="node-editor__label">
         Line Location:
         <input className="node-editor__input" type="number" value={lineLocation} onChange={handleLineLocationChange} />
       </label>
+      <label className="node-editor__label">
+        Captures:
+        <input className="node-editor__input" type="text" value={captures} placeholder="count, ptr->field" onChange={handleCapturesChange} />
+      </label>
+      <label className="node-editor__label">
+        Only when:
+        <input className="node-editor__input" type="text" value={when} placeholder="size > 4096" onChange={handleWhenChange} />
+      </label>
       {
         props.mode === 'add' ? (
           <button className="node-editor__button" onClick={handleAddNode}>Add Node</button>
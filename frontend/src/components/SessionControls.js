@@ -0,0 +1,47 @@
+import React, { useState } from 'react';
+import { useRemoteResource, callRemote } from '../util.js';
+
+// Save the current graph as a named session in the recording, or
+// restore one that a teammate saved
+const SessionControls = (props) => {
+  const [name, setName] = useState("");
+  const [selected, setSelected] = useState("");
+  const [sessions, setSessions] = useRemoteResource({ names: [] },
+    { trace_id: props.traceId }, 'sessions', [props.traceId])
+
+  const refreshSessions = () => {
+    callRemote({ trace_id: props.traceId }, 'sessions')
+      .then(resp => resp.json())
+      .then(resp => setSessions(resp));
+  }
+  const handleSave = () => {
+    callRemote({ trace_id: props.traceId, name: name }, 'save_session')
+      .then(_ => refreshSessions())
+      .catch(() => {});
+  }
+  const handleLoad = () => {
+    if (selected == "") {
+      return;
+    }
+    props.setIsLoading(true);
+    callRemote({ trace_id: props.traceId, name: selected }, 'load_session')
+      .then(_ => props.onLoaded())
+      .catch(() => {})
+      .finally(() => props.setIsLoading(false));
+  }
+  return (
+    <div className="session-controls">
+      <input type="text" placeholder="session name" value={name} onChange={(e) => setName(e.target.value)} />
+      <button onClick={handleSave} disabled={name == ""}>{"Save Session"}</button>
+      <select value={selected} onChange={(e) => setSelected(e.target.value)}>
+        <option value="">{"[choose a session]"}</option>
+        {sessions.names.map((session) => (
+          <option key={session} value={session}>{session}</option>
+        ))}
+      </select>
+      <button onClick={handleLoad} disabled={selected == ""}>{"Load Session"}</button>
+    </div>
+  );
+}
+
+export default SessionControls;
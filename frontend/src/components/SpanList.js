@@ -0,0 +1,72 @@
+import React from 'react';
+import { useRemoteResource } from '../util.js';
+
+// Turn the flat list of spans (ordered by thread then start, with depths)
+// into one tree per thread
+const buildTrees = (spans) => {
+  let threads = {};
+  let stacks = {};
+  for (const span of spans) {
+    if (!(span.tid in threads)) {
+      threads[span.tid] = [];
+      stacks[span.tid] = [];
+    }
+    // stack[d] is the last span seen at depth d
+    let stack = stacks[span.tid];
+    stack.length = Math.min(stack.length, span.depth);
+    let node = { span: span, children: [] };
+    if (stack.length == 0) {
+      threads[span.tid].push(node);
+    } else {
+      stack[stack.length - 1].children.push(node);
+    }
+    stack.push(node);
+  }
+  return threads;
+}
+
+const SpanNode = ({ node, showHeatMap }) => {
+  let span = node.span;
+  let duration = span.end == null ? "never ended" :
+    (span.duration_ms == null ? "?" : `${span.duration_ms} ms`);
+  let end = span.end == null ? "" : ` - ${span.end.frame_time}`;
+  let label = `${span.name} (${duration}, frame time ${span.start.frame_time}${end})`;
+  let heatMapButton = span.end != null &&
+    <button className="span-list__heat-map" title="Count the lines run in this span's function"
+      onClick={(e) => { e.preventDefault(); showHeatMap(span); }}>{"🔥"}</button>;
+  if (node.children.length == 0) {
+    return <div className="span-list__leaf">{label}{heatMapButton}</div>;
+  }
+  return (
+    <details className="span-list__span">
+      <summary>{label}{heatMapButton}</summary>
+      {node.children.map((child, i) => <SpanNode key={i} node={child} showHeatMap={showHeatMap} />)}
+    </details>
+  );
+}
+
+const SpanList = (props) => {
+  const [response, _set] = useRemoteResource({ spans: [] },
+    { "trace_id": props.traceId },
+    'spans', [props.traceId, props.nodesData])
+
+  if (response.spans.length == 0) {
+    return null;
+  }
+  let threads = buildTrees(response.spans);
+  return (
+    <div className="box-wrapper">
+      <h3>{"Spans"}</h3>
+      <div className="span-list">
+        {Object.keys(threads).map((tid) => (
+          <details className="span-list__span" key={tid} open>
+            <summary>{`Thread ${tid}`}</summary>
+            {threads[tid].map((node, i) => <SpanNode key={i} node={node} showHeatMap={props.showHeatMap} />)}
+          </details>
+        ))}
+      </div>
+    </div>
+  );
+}
+
+export default SpanList;
@@ -0,0 +1,60 @@
+import React from 'react';
+import { useRemoteResource } from '../util.js';
+import { Timeline } from 'react-svg-timeline'
+
+// The events each thread hit, one lane per thread. Clicking an event
+// selects its node like clicking it in the graph.
+const ThreadLanes = (props) => {
+  const [response, _set] = useRemoteResource({ lanes: [] },
+    { "trace_id": props.traceId },
+    'thread_lanes', [props.traceId, props.nodesData])
+
+  if (response.lanes.length == 0) {
+    return null;
+  }
+  let nodeOfAddr = {};
+  for (const [id, node] of Object.entries(props.nodesData.nodes || {})) {
+    nodeOfAddr[node.address] = { id: Number(id), name: node.name };
+  }
+  let times = props.generalInfo.traces[props.traceId].frame_time_map.times;
+  let lanes = [];
+  let events = [];
+  // Hits of an address in the same frame time are drawn once
+  let nodeOfEvent = {};
+  for (const lane of response.lanes) {
+    const laneId = `thread-${lane.tid}`;
+    lanes.push({ laneId: laneId, label: `Thread ${lane.tid}` });
+    for (const stamp of lane.events) {
+      const eventId = `${lane.tid}-${stamp.frame_time}-${stamp.addr}`;
+      if (eventId in nodeOfEvent || times[stamp.frame_time] == null) {
+        continue;
+      }
+      let node = nodeOfAddr[stamp.addr];
+      nodeOfEvent[eventId] = node;
+      events.push({
+        eventId: eventId,
+        laneId: laneId,
+        startTimeMillis: times[stamp.frame_time],
+        tooltip: `${node ? node.name : stamp.addr} (frame time ${stamp.frame_time})`,
+      });
+    }
+  }
+  const onEventClick = (eventId) => {
+    let node = nodeOfEvent[eventId];
+    if (node) {
+      props.updateCurrentNode({ id: node.id, is_raw: false });
+    }
+  }
+  const dateFormat = (ms) => new Date(ms).toJSON();
+  return (
+    <div className="box-wrapper">
+      <h3>{"Threads"}</h3>
+      <div className="lane-viewer">
+        <Timeline className='lane-viewer-timeline' width={600} height={60 + 40 * lanes.length}
+          events={events} lanes={lanes} dateFormat={dateFormat} onEventClick={onEventClick} />
+      </div>
+    </div>
+  );
+}
+
+export default ThreadLanes;
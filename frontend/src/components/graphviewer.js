@@ -37,8 +37,8 @@ const GraphViewer = (props) => {
   // }, []);
   const id = useMemo(getId, []);
   const [graphVer, setGraphVer] = React.useState(0);
-  const [dotSrc, _setDotSrc] = useRemoteResource({ version: 0, dot: `digraph { graph [label="No Loaded Graph"] }` }, {}, 'current_graph', [props.nodesData, graphVer]);
-  const [initialSettings, setSettings] = useRemoteResource({show_unreachable_nodes:false, selected_node_id:0}, {}, 'get_settings', []);
+  const [dotSrc, _setDotSrc] = useRemoteResource({ version: 0, dot: `digraph { graph [label="No Loaded Graph"] }` }, { trace_id: props.traceId }, 'current_graph', [props.nodesData, graphVer, props.settingsVersion]);
+  const [initialSettings, setSettings] = useRemoteResource({show_unreachable_nodes:false, partition_by_thread:true, selected_node_id:0}, {}, 'get_settings', [props.settingsVersion]);
 
   const defaultOptions = {
     fit: false,
@@ -116,6 +116,16 @@ const GraphViewer = (props) => {
 
             })))
   }
+  const handlePartitionByThread=(checked)=> {
+        callRemote({}, "get_settings")
+          .then(response => response.json())
+          .then(old_settings => { old_settings.partition_by_thread = checked; return old_settings })
+          .then(new_settings => {setSettings(new_settings); return new_settings})
+          .then(new_settings => callRemote({ "settings": new_settings }, "set_settings")
+            .then(_ => unstable_batchedUpdates(() => {
+              setGraphVer(graphVer + 1)
+            })))
+  }
   useEffect(() => {
     if (selectVisualUpdate){
       const gviz = graphviz(`#${id}`, { ...defaultOptions });
@@ -156,6 +166,13 @@ const GraphViewer = (props) => {
           </div>
         </div>
         <br/>
+        <div style={{display:"inline-flex", gap:"20px"}}>
+          <p> Separate sequence per thread: </p>
+          <div style={{padding:"0.9em 0em"}}>
+          <Switch onChange={handlePartitionByThread} checked={initialSettings.partition_by_thread} />
+          </div>
+        </div>
+        <br/>
         <div style={{display:"inline-flex", gap:"20px"}}>
           <p> Rerender graph on updates: </p>
           <div style={{padding:"0.9em 0em"}}>
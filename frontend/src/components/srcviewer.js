@@ -1,6 +1,6 @@
 import { Timeline } from 'react-svg-timeline'
 import {useState, useEffect, useRef} from 'react'
-import {useRemoteResource} from '../util.js';
+import {useRemoteResource, callRemote, apiErrorText} from '../util.js';
 import {Prism as SyntaxHighlighter } from 'react-syntax-highlighter';
 //import { atomOneDark } from 'react-syntax-highlighter/dist/esm/styles/hljs';
 import { a11yDark } from "react-syntax-highlighter/dist/cjs/styles/prism";
@@ -20,14 +20,15 @@ const SrcViewer = (props) => {
   useEffect(()=>{setCenteredLine(props.currentFileLineNum-(numLines/2))},[props.currentFileLineNum]);
 
 
-  const [allFiles, _setAllFiles] = useRemoteResource({files:["[none selected]"]},{}, 'source_files');
+  const [allFiles, _setAllFiles] = useRemoteResource({files:["[none selected]"]},{trace_id: props.traceId}, 'source_files');
 
   const onUpdate = (new_val) => {
     props.setCurrentFileLineNum(0);
     props.setCurrentFilePath(new_val)
   };
-  const [data, _setData] = useRemoteResource({data:""}, {file_name: currentFile.file}, "source_file", [props.currentFilePath]);
-  let lines = data.data.split("\n");
+  const [data, _setData, dataError] = useRemoteResource({data:""}, {trace_id: props.traceId, file_name: currentFile.file}, "source_file", [props.currentFilePath]);
+  // Files the server refuses or can not read come back as an error
+  let lines = (dataError ? `[${apiErrorText(dataError)}]` : data.data).split("\n");
   
   let minLine = Math.max(0,centeredLine);
   let maxLine = Math.min(lines.length-1, centeredLine+numLines);
@@ -36,6 +37,14 @@ const SrcViewer = (props) => {
     usedLines.push("");
   }
   
+  // Lines of the heat map are shaded by how often they ran
+  let heatLines = (props.heatMap && props.heatMap.source_file == currentFile.file) ?
+    props.heatMap.line_vs_times_executed : {};
+  let maxHeat = Math.max(1, ...Object.values(heatLines));
+  // Measured lines are green if they ran and red if they did not
+  let coveredFile = props.coverage && props.coverage.files.find(file => file.file == currentFile.file);
+  let coverageLines = coveredFile ? coveredFile.lines : {};
+
   let toDisplay = usedLines.join("\n");
   let numLinesInFile = lines.length;
 
@@ -70,6 +79,18 @@ const SrcViewer = (props) => {
      setShowUpdateModuleModal(true);
      setShowDropdown(false);
   }
+  const writeAnnotations = () => {
+    setShowDropdown(false);
+    callRemote({trace_id: props.traceId}, 'write_annotations')
+      .then(resp => resp.json())
+      .then(resp => {
+        let summary = resp.written.map(file => `Wrote ${file}`)
+          .concat(resp.conflicts.map(c => `Not written ${c.file}: ${c.reason}`))
+          .concat(resp.skipped.map(s => `Skipped ${s}`));
+        alert(summary.length == 0 ? "Every annotation is already up to date" : summary.join("\n"));
+      })
+      .catch(() => {});
+  }
   const handleClick = (e) => {
     if (dropdownRef.current && !dropdownRef.current.contains(e.target)) {
       setShowDropdown(false);
@@ -85,6 +106,9 @@ const SrcViewer = (props) => {
           <div className="src-viewer-dropdown-option" onClick={addModule}>
             ➕ Add / Update Module 
           </div>
+          <div className="src-viewer-dropdown-option" onClick={writeAnnotations}>
+            💾 Write Annotations to Source
+          </div>
         </div>
       )}
 
@@ -101,6 +125,12 @@ const SrcViewer = (props) => {
       list={allFiles.files.concat(["[none selected]"])}
       />
       </div>
+    {Object.keys(heatLines).length > 0 &&
+      <div className="src-viewer-heat-map"
+        title="Counted per basic block, so a line can get the count of a jump that skips it">
+        {"Shading how often each line ran in the span "}
+        <button onClick={props.clearHeatMap}>{"Clear"}</button>
+      </div>}
     <div className="src-inner" 
       onWheel={handleScroll}
       onScroll={(e)=>{e.preventDefault()}}  
@@ -115,8 +145,14 @@ const SrcViewer = (props) => {
         wrapLines={true}
         lineProps={(lineNumber) => {
           const style = { display: "block", width: "fit-content" };
+          let heat = heatLines[lineNumber];
+          let covered = coverageLines[lineNumber];
           if (currentFile.line_num == lineNumber) {
             style.backgroundColor = "#ca0a0a";
+          } else if (heat != null) {
+            style.backgroundColor = `rgba(255, 140, 0, ${0.15 + 0.6 * heat / maxHeat})`;
+          } else if (covered != null) {
+            style.backgroundColor = covered > 0 ? "rgba(0, 160, 0, 0.3)" : "rgba(200, 0, 0, 0.3)";
           }
           //style.onClick = () => {console.log("test")};
           let onClick = () => {console.log("test")}
@@ -124,7 +160,9 @@ const SrcViewer = (props) => {
             setClickedLineNum(lineNumber);
             handleRightClick(e);
           };
-          return { style, onContextMenu};
+          let timesRun = heat != null ? heat : covered;
+          let title = timesRun == null ? undefined : `Ran ${timesRun} times`;
+          return { style, onContextMenu, title};
         }}
         
       >
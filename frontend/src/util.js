@@ -15,25 +15,74 @@ function fetchRetry(url, delay, tries, fetchOptions = {}) {
     }
     return fetch(url,fetchOptions).catch(onError);
 }
+// Set by /config.js when the frontend is served by explorant. The
+// development server falls back to the default port.
+const server = window.explorantConfig
+  ? `${window.location.protocol}//${window.location.hostname}:${window.explorantConfig.port}/`
+  : 'http://127.0.0.1:12000/';
+// Servers started with --auth are opened with ?token=. The server also
+// sets a cookie, but that is not sent to it from the development server.
+const token = new URLSearchParams(window.location.search).get('token');
+const headers = token
+  ? {'Content-Type': 'application/json', 'Authorization': `Bearer ${token}`}
+  : {'Content-Type': 'application/json'};
+
+// Failed requests respond with an ApiError: {kind, message, location}
+export const apiErrorText = (error) => {
+  if (error.location) {
+    return `${error.message} (${error.location.file}:${error.location.line_num})`;
+  }
+  return error.message;
+};
+const readApiError = (response) => response.json()
+  .catch(() => ({kind: "internal", message: `${response.status} ${response.statusText}`, location: null}));
+
+// The third value is the ApiError of the last request, or null
 export const useRemoteResource = (defaultVal, requestBody, endpoint, effectHook=[]) => {
   const [count, setCount] = useState(defaultVal);
+  const [error, setError] = useState(null);
   const requestOptions = {
     method: 'POST',
-    headers: {'Content-Type': 'application/json'},
+    headers: headers,
     body: JSON.stringify(requestBody)
   };
   useEffect(()=>{
-    fetchRetry('http://127.0.0.1:12000/'+endpoint,200,10000,requestOptions)
-      .then(response=>response.json())
-      .then(data=>setCount(data))
+    fetchRetry(server+endpoint,200,10000,requestOptions)
+      .then(response=>{
+        if (response.ok) {
+          return response.json().then(data=>{setError(null); setCount(data)});
+        }
+        return readApiError(response).then(apiError=>{
+          console.error(`${endpoint}: ${apiErrorText(apiError)}`);
+          setError(apiError);
+        });
+      })
   },effectHook);
-  return [count,setCount];
+  return [count,setCount,error];
+};
+// Calls onEvent with every ServerEvent pushed by /events: graph and
+// settings versions and job progress. Returns a function that stops.
+// EventSource can not send headers, so the token goes in the URL.
+export const subscribeEvents = (onEvent) => {
+  const source = new EventSource(server + 'events' + (token ? `?token=${token}` : ''));
+  source.onmessage = (message) => onEvent(JSON.parse(message.data));
+  return () => source.close();
 };
 export const callRemote = (requestBody, endpoint) => {
   const requestOptions = {
     method: 'POST',
-    headers: {'Content-Type': 'application/json'},
+    headers: headers,
     body: JSON.stringify(requestBody)
   };
-  return fetch('http://127.0.0.1:12000/'+endpoint,requestOptions)
+  // Errors are shown here, callers only see the rejection
+  return fetch(server+endpoint,requestOptions)
+    .then(response=>{
+      if (response.ok) {
+        return response;
+      }
+      return readApiError(response).then(apiError=>{
+        alert(apiErrorText(apiError));
+        throw apiError;
+      });
+    })
 };
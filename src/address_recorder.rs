@@ -1,3 +1,7 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
 use crate::shared_structs::*;
 // I use a dual layered system to record entries in this table
 // The first is a map of frametime -> FtBin
@@ -6,6 +10,7 @@ use crate::shared_structs::*;
 //
 // The cool idea around this implementation is that it allows the user
 // to sparely record TimeStamps while densely recording addresses.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AddressRecorder {
     records: Vec<FtBin>,
     write_head: usize,
@@ -17,9 +22,18 @@ pub struct AddressRecorder {
 //
 //impl !Sync for AddressRecorder {}
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 struct FtBin {
     pub addresses: Vec<usize>,
+    // The thread that hit each address
+    pub threads: Vec<ThreadID>,
+    // Which hit of its address in the frame time each address was.
+    // Hits whose condition failed are counted but not recorded, so
+    // this is the breakpoint hit a replay has to stop at.
+    pub instances: Vec<usize>,
+    // index in addresses -> values captured at that hit.
+    // Sparse as most events capture nothing.
+    pub captures: BTreeMap<usize, Vec<CapturedValue>>,
     pub indexes: Vec<(TimeStamp, usize)>,
 }
 
@@ -45,6 +59,9 @@ impl AddressRecorder {
         debug_assert!(!self.is_writing_ftbin);
         self.records[frame_time].indexes.clear();
         self.records[frame_time].addresses.clear();
+        self.records[frame_time].threads.clear();
+        self.records[frame_time].instances.clear();
+        self.records[frame_time].captures.clear();
 
         self.write_head = frame_time;
         self.records[frame_time]
@@ -52,9 +69,33 @@ impl AddressRecorder {
             .push((TimeStamp::new_at_ft(frame_time), 0));
         self.is_writing_ftbin = true;
     }
+    // For when the thread is not known
     pub fn insert_address(&mut self, address: usize) {
+        self.insert_address_from(address, UNKNOWN_THREAD);
+    }
+    // For when every hit is recorded
+    pub fn insert_address_from(&mut self, address: usize, thread: ThreadID) {
+        let bin = &self.records[self.write_head];
+        let instance = 1 + bin
+            .addresses
+            .iter()
+            .filter(|addr| **addr == address)
+            .count();
+        self.insert_hit(address, thread, instance);
+    }
+    pub fn insert_hit(&mut self, address: usize, thread: ThreadID, instance: usize) {
+        debug_assert!(self.is_writing_ftbin);
+        let bin = &mut self.records[self.write_head];
+        bin.addresses.push(address);
+        bin.threads.push(thread);
+        bin.instances.push(instance);
+    }
+    // Attach values to the last inserted address
+    pub fn insert_captures(&mut self, values: Vec<CapturedValue>) {
         debug_assert!(self.is_writing_ftbin);
-        self.records[self.write_head].addresses.push(address);
+        let bin = &mut self.records[self.write_head];
+        debug_assert!(!bin.addresses.is_empty());
+        bin.captures.insert(bin.addresses.len() - 1, values);
     }
     pub fn insert_timestamp(&mut self, stamp: TimeStamp) {
         debug_assert!(self.is_writing_ftbin);
@@ -69,6 +110,62 @@ impl AddressRecorder {
         self.is_writing_ftbin = false;
         // placeholder
     }
+    pub fn max_frame_time(&self) -> usize {
+        self.records.len() - 2
+    }
+    // Drop every recorded address that keep returns false for.
+    // Index entries are moved so that they still point at the same
+    // hit and entries for dropped addresses are removed.
+    pub fn retain_addresses(&mut self, keep: impl Fn(usize) -> bool) {
+        debug_assert!(!self.is_writing_ftbin);
+        for bin in &mut self.records {
+            if bin.addresses.iter().all(|addr| keep(*addr)) {
+                continue;
+            }
+            // kept_before[i] = number of kept addresses in addresses[..i]
+            let mut kept_before = Vec::with_capacity(bin.addresses.len() + 1);
+            kept_before.push(0);
+            for addr in &bin.addresses {
+                kept_before.push(kept_before.last().unwrap() + keep(*addr) as usize);
+            }
+            bin.indexes = bin
+                .indexes
+                .drain(..)
+                .filter(|(stamp, _)| stamp.addr.map_or(true, |addr| keep(addr)))
+                .map(|(stamp, loc)| (stamp, kept_before[loc.min(kept_before.len() - 1)]))
+                .collect();
+            let kept: Vec<usize> = (0..bin.addresses.len())
+                .filter(|index| keep(bin.addresses[*index]))
+                .collect();
+            bin.threads = kept.iter().map(|index| bin.threads[*index]).collect();
+            bin.instances = kept.iter().map(|index| bin.instances[*index]).collect();
+            bin.addresses = kept.iter().map(|index| bin.addresses[*index]).collect();
+            bin.captures = std::mem::take(&mut bin.captures)
+                .into_iter()
+                .filter(|(index, _)| kept_before[*index + 1] != kept_before[*index])
+                .map(|(index, values)| (kept_before[index], values))
+                .collect();
+        }
+    }
+    // Copy the bins of other into self for every frame time where
+    // self has no addresses. Returns the frame times where both have
+    // addresses, as the order of the hits between them is unknown.
+    pub fn merge_from(&mut self, other: &AddressRecorder) -> Vec<usize> {
+        debug_assert!(!self.is_writing_ftbin);
+        debug_assert!(self.records.len() == other.records.len());
+        let mut conflicts = Vec::new();
+        for (frame_time, other_bin) in other.records.iter().enumerate() {
+            if other_bin.addresses.is_empty() {
+                continue;
+            }
+            if self.records[frame_time].addresses.is_empty() {
+                self.records[frame_time] = other_bin.clone();
+            } else {
+                conflicts.push(frame_time);
+            }
+        }
+        conflicts
+    }
     //TODO test this function
     pub fn get_all_addresses<'a>(&'a self) -> anyhow::Result<AddrIter<'a>> {
         let mut to_ret = AddrIter::new();
@@ -77,22 +174,64 @@ impl AddressRecorder {
         }
         Ok(to_ret)
     }
+    // The addresses hit by each thread, in order
+    pub fn get_addresses_by_thread(&self) -> BTreeMap<ThreadID, Vec<usize>> {
+        let mut ret: BTreeMap<ThreadID, Vec<usize>> = BTreeMap::new();
+        for bin in &self.records {
+            for (addr, thread) in bin.addresses.iter().zip(&bin.threads) {
+                ret.entry(*thread).or_default().push(*addr);
+            }
+        }
+        ret
+    }
+    // Every hit as a TimeStamp, grouped by the thread that hit it.
+    // instance_of_addr is the recorded instance like in
+    // get_addr_occurrences so the stamps can be used to start gdb.
+    pub fn get_thread_lanes(&self) -> BTreeMap<ThreadID, Vec<TimeStamp>> {
+        let mut ret: BTreeMap<ThreadID, Vec<TimeStamp>> = BTreeMap::new();
+        for bin in &self.records {
+            if bin.indexes.len() == 0 {
+                continue;
+            }
+            let ft = &bin.indexes[0].0;
+            for ((addr, thread), instance) in
+                bin.addresses.iter().zip(&bin.threads).zip(&bin.instances)
+            {
+                ret.entry(*thread).or_default().push(TimeStamp {
+                    frame_time: ft.frame_time,
+                    addr: Some(*addr),
+                    instance_of_addr: Some(*instance),
+                });
+            }
+        }
+        ret
+    }
     pub fn get_addr_occurrences(&self, target_addr: usize) -> Vec<TimeStamp> {
+        self.get_addr_occurrences_with_captures(target_addr)
+            .into_iter()
+            .map(|(stamp, _)| stamp)
+            .collect()
+    }
+    pub fn get_addr_occurrences_with_captures(
+        &self,
+        target_addr: usize,
+    ) -> Vec<(TimeStamp, Vec<CapturedValue>)> {
         let mut ret = Vec::new();
         for bin in &self.records {
             if bin.indexes.len() == 0 {
                 continue;
             }
             let ft = &bin.indexes[0].0;
-            let mut count = 0;
-            for addr in &bin.addresses {
+            for (index, addr) in bin.addresses.iter().enumerate() {
                 if *addr == target_addr {
-                    count += 1;
-                    ret.push(TimeStamp {
-                        frame_time: ft.frame_time,
-                        addr: Some(*addr),
-                        instance_of_addr: Some(count),
-                    });
+                    ret.push((
+                        TimeStamp {
+                            frame_time: ft.frame_time,
+                            addr: Some(*addr),
+                            instance_of_addr: Some(bin.instances[index]),
+                        },
+                        bin.captures.get(&index).cloned().unwrap_or_default(),
+                    ));
                 }
             }
         }
@@ -329,4 +468,148 @@ mod tests {
         dbg!(&result);
         assert_eq!(result, vec![1, 3, 1000, 4]);
     }
+    #[test]
+    fn serde_roundtrip() {
+        let mut ar = AddressRecorder::new(3);
+        ar.reset_ft_for_writing(1);
+        ar.insert_address(7);
+        ar.insert_address(8);
+        ar.finished_writing_ft();
+        ar.reset_ft_for_writing(3);
+        ar.insert_address(7);
+        ar.finished_writing_ft();
+        let data = serde_json::to_string(&ar).unwrap();
+        let loaded: AddressRecorder = serde_json::from_str(&data).unwrap();
+        let result: Vec<usize> = loaded.get_all_addresses().unwrap().collect();
+        assert_eq!(result, vec![7, 8, 7]);
+        assert_eq!(
+            loaded.get_addr_occurrences(7),
+            ar.get_addr_occurrences(7)
+        );
+    }
+    #[test]
+    fn retain_moves_indexes() {
+        let mut ar = AddressRecorder::new(2);
+        let stamp = TimeStamp {
+            frame_time: 1,
+            addr: Some(3),
+            instance_of_addr: Some(1),
+        };
+        let dropped = TimeStamp {
+            frame_time: 1,
+            addr: Some(2),
+            instance_of_addr: Some(1),
+        };
+        ar.reset_ft_for_writing(1);
+        ar.insert_address(1);
+        ar.insert_address(2);
+        ar.insert_timestamp(dropped.clone());
+        ar.insert_address(3);
+        ar.insert_timestamp(stamp.clone());
+        ar.insert_address(4);
+        ar.finished_writing_ft();
+        ar.retain_addresses(|addr| addr != 2);
+        let result: Vec<usize> = ar.get_all_addresses().unwrap().collect();
+        assert_eq!(result, vec![1, 3, 4]);
+        let result: Vec<usize> = ar
+            .get_addresses_in(&TimeRange {
+                start: TimeStamp::new_at_ft(1),
+                end: stamp,
+            })
+            .unwrap()
+            .collect();
+        assert_eq!(result, vec![1]);
+        assert!(ar
+            .get_addresses_in(&TimeRange {
+                start: TimeStamp::new_at_ft(1),
+                end: dropped,
+            })
+            .is_err());
+    }
+    #[test]
+    fn merge_reports_conflicts() {
+        let mut ar = AddressRecorder::new(3);
+        ar.reset_ft_for_writing(1);
+        ar.insert_address(1);
+        ar.finished_writing_ft();
+        ar.reset_ft_for_writing(2);
+        ar.insert_address(1);
+        ar.finished_writing_ft();
+        let mut other = AddressRecorder::new(3);
+        other.reset_ft_for_writing(2);
+        other.insert_address(5);
+        other.finished_writing_ft();
+        other.reset_ft_for_writing(3);
+        other.insert_address(5);
+        other.finished_writing_ft();
+        assert_eq!(ar.merge_from(&other), vec![2]);
+        let result: Vec<usize> = ar.get_all_addresses().unwrap().collect();
+        assert_eq!(result, vec![1, 1, 5]);
+    }
+    #[test]
+    fn addresses_by_thread() {
+        let mut ar = AddressRecorder::new(2);
+        ar.reset_ft_for_writing(1);
+        ar.insert_address_from(1, 10);
+        ar.insert_address_from(2, 11);
+        ar.insert_address_from(1, 10);
+        ar.finished_writing_ft();
+        ar.reset_ft_for_writing(2);
+        ar.insert_address_from(3, 11);
+        ar.finished_writing_ft();
+        let by_thread = ar.get_addresses_by_thread();
+        assert_eq!(by_thread[&10], vec![1, 1]);
+        assert_eq!(by_thread[&11], vec![2, 3]);
+        ar.retain_addresses(|addr| addr != 2);
+        assert_eq!(ar.get_addresses_by_thread()[&11], vec![3]);
+        let lanes = ar.get_thread_lanes();
+        assert_eq!(
+            lanes[&10][1],
+            TimeStamp {
+                frame_time: 1,
+                addr: Some(1),
+                instance_of_addr: Some(2),
+            }
+        );
+    }
+    #[test]
+    fn captures_follow_their_hit() {
+        let value = |v: &str| CapturedValue {
+            expression: "x".into(),
+            value: v.into(),
+        };
+        let mut ar = AddressRecorder::new(1);
+        ar.reset_ft_for_writing(1);
+        ar.insert_address(1);
+        ar.insert_captures(vec![value("first")]);
+        ar.insert_address(2);
+        ar.insert_address(1);
+        ar.insert_captures(vec![value("second")]);
+        ar.finished_writing_ft();
+        ar.retain_addresses(|addr| addr != 2);
+        let occurrences = ar.get_addr_occurrences_with_captures(1);
+        assert_eq!(occurrences.len(), 2);
+        assert_eq!(occurrences[0].1, vec![value("first")]);
+        assert_eq!(occurrences[1].1, vec![value("second")]);
+        assert_eq!(occurrences[1].0.instance_of_addr, Some(2));
+    }
+    #[test]
+    fn conditional_hits_keep_their_instance() {
+        // Only the 2nd and 4th hits of 1 passed their condition
+        let mut ar = AddressRecorder::new(1);
+        ar.reset_ft_for_writing(1);
+        ar.insert_hit(1, 10, 2);
+        ar.insert_hit(2, 10, 1);
+        ar.insert_hit(1, 11, 4);
+        ar.finished_writing_ft();
+        let instances: Vec<Option<usize>> = ar
+            .get_addr_occurrences(1)
+            .iter()
+            .map(|stamp| stamp.instance_of_addr)
+            .collect();
+        assert_eq!(instances, vec![Some(2), Some(4)]);
+        ar.retain_addresses(|addr| addr != 2);
+        assert_eq!(ar.get_thread_lanes()[&11][0].instance_of_addr, Some(4));
+        assert_eq!(ar.get_addr_occurrences(1)[1].instance_of_addr, Some(4));
+    }
 }
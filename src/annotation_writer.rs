@@ -0,0 +1,715 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+
+use regex::{NoExpand, Regex};
+
+use crate::file_parsing::{self, Annotation, ANNOTATION_PATTERN, SPAN_BEGIN_SUFFIX, SPAN_END_SUFFIX};
+use crate::graph_builder::GraphBuilder;
+use crate::shared_structs::{
+    AnnotationConflict, AnnotationOrigin, GraphNode, TraceID, WriteAnnotationsRequest,
+    WriteAnnotationsResponse,
+};
+
+// Writes the nodes and modules edited in the UI back into the
+// [[{...}]] comments of the source files.
+//
+// Annotations that were not edited are left exactly as they are.
+// Edited ones are rewritten in place, deleted ones are removed and
+// new or moved ones are put next to the line of their node.
+
+// A source file as it was when its annotations were last read or written
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSnapshot {
+    hash: u64,
+    // Line of each module, event, flow and span annotation mapped to
+    // the line its node was on (None for modules)
+    annotations: BTreeMap<u32, Option<u32>>,
+    // file_lines[n] is where line n of the DWARF data is now.
+    // Writing annotations moves lines until the program is rebuilt.
+    file_lines: Vec<u32>,
+}
+impl SourceSnapshot {
+    pub fn new(text: &str) -> Self {
+        let line_count = text.lines().count() as u32;
+        Self {
+            hash: hash_text(text),
+            annotations: BTreeMap::new(),
+            file_lines: (0..=line_count + 1).collect(),
+        }
+    }
+    pub fn record_annotation(&mut self, line: u32, node_line: Option<u32>) {
+        self.annotations.insert(line, node_line);
+    }
+    fn file_line(&self, dwarf_line: u32) -> u32 {
+        let last = self.file_lines.len() - 1;
+        match self.file_lines.get(dwarf_line as usize) {
+            Some(line) => *line,
+            None => self.file_lines[last] + dwarf_line - last as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Owner {
+    Node(usize),
+    Module(String),
+}
+// Lines of the file as it is now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    Keep(u32),
+    Before(u32),
+    After(u32),
+}
+#[derive(Debug, Clone, PartialEq)]
+struct Planned {
+    owner: Owner,
+    annotation: Annotation,
+    placement: Placement,
+    node_line: Option<u32>,
+}
+
+pub fn write_annotations(graph_builder: &mut GraphBuilder) -> WriteAnnotationsResponse {
+    let mut response = WriteAnnotationsResponse {
+        written: Vec::new(),
+        conflicts: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let plans = plan(graph_builder, &mut response.skipped);
+    for (file, planned) in plans {
+        let conflict = |reason: String| AnnotationConflict {
+            file: file.clone(),
+            reason,
+        };
+        let snapshot = &graph_builder.sources[&file];
+        let text = match std::fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) => {
+                response.conflicts.push(conflict(e.to_string()));
+                continue;
+            }
+        };
+        if hash_text(&text) != snapshot.hash {
+            response
+                .conflicts
+                .push(conflict("Changed since its annotations were read".into()));
+            continue;
+        }
+        let lines: Vec<&str> = text.lines().collect();
+        let syntax = comment_syntax(&file, &lines, snapshot.annotations.keys());
+        let rewrite = rewrite(&text, snapshot, &planned, &syntax);
+        if rewrite.text == text {
+            continue;
+        }
+        if let Err(e) = std::fs::write(&file, &rewrite.text) {
+            response.conflicts.push(conflict(e.to_string()));
+            continue;
+        }
+        log::info!("Wrote annotations to {}", file.to_string_lossy());
+
+        let mut written = SourceSnapshot::new(&rewrite.text);
+        written.file_lines = snapshot
+            .file_lines
+            .iter()
+            .map(|line| rewrite.file_lines[*line as usize])
+            .collect();
+        for (planned, line) in planned.iter().zip(rewrite.placed) {
+            written.record_annotation(line, planned.node_line);
+            let origin = AnnotationOrigin::Source {
+                file: file.clone(),
+                line,
+            };
+            match &planned.owner {
+                Owner::Node(key) => graph_builder.nodes.get_mut(key).unwrap().origin = origin,
+                Owner::Module(name) => graph_builder.modules.get_mut(name).unwrap().origin = origin,
+            }
+        }
+        graph_builder.sources.insert(file.clone(), written);
+        response.written.push(file);
+    }
+    response
+}
+
+// Where every annotation goes, by file. Files that had annotations
+// are always included so that deleted ones are removed.
+fn plan(graph_builder: &GraphBuilder, skipped: &mut Vec<String>) -> BTreeMap<PathBuf, Vec<Planned>> {
+    let sources = &graph_builder.sources;
+    let mut plans: BTreeMap<PathBuf, Vec<Planned>> = sources
+        .iter()
+        .filter(|(_, snapshot)| !snapshot.annotations.is_empty())
+        .map(|(file, _)| (file.clone(), Vec::new()))
+        .collect();
+    let is_written = |origin: &AnnotationOrigin| {
+        !matches!(origin, AnnotationOrigin::Sidecar | AnnotationOrigin::Functions)
+    };
+
+    let mut keys: Vec<&usize> = graph_builder.nodes.keys().collect();
+    keys.sort();
+    for key in keys {
+        let node = &graph_builder.nodes[key];
+        if !is_written(&node.origin) {
+            continue;
+        }
+        let Some(annotation) = node_annotation(node) else {
+            skipped.push(format!("{} is a {} node, which has no annotation", node.FQN, node.node_type));
+            continue;
+        };
+        let file = &node.location.file;
+        let Some(snapshot) = sources.get(file) else {
+            skipped.push(format!("{} is in {}, which was not read", node.FQN, file.to_string_lossy()));
+            continue;
+        };
+        let line = node.location.line_num;
+        let placement = match &node.origin {
+            AnnotationOrigin::Source {
+                file: origin_file,
+                line: origin_line,
+            } if origin_file == file && snapshot.annotations.get(origin_line) == Some(&Some(line)) => {
+                Placement::Keep(*origin_line)
+            }
+            // A flow annotation marks the line before it
+            _ if node.node_type == "Flow" => Placement::After(snapshot.file_line(line)),
+            _ => Placement::Before(snapshot.file_line(line)),
+        };
+        plans.entry(file.clone()).or_default().push(Planned {
+            owner: Owner::Node(*key),
+            annotation,
+            placement,
+            node_line: Some(line),
+        });
+    }
+
+    let mut names: Vec<&String> = graph_builder.modules.keys().collect();
+    names.sort();
+    for name in names {
+        let module = &graph_builder.modules[name];
+        // The root module is never declared
+        if name.is_empty() || !is_written(&module.origin) {
+            continue;
+        }
+        let annotation = Annotation::Module {
+            name: name.clone(),
+            parent_module: module.parent.clone().filter(|parent| !parent.is_empty()),
+            comment: None,
+        };
+        let (file, placement) = match &module.origin {
+            AnnotationOrigin::Source { file, line } if plans.contains_key(file) => {
+                (file.clone(), Placement::Keep(*line))
+            }
+            // Above the first of its nodes
+            _ => {
+                let first = plans.iter().find_map(|(file, planned)| {
+                    planned
+                        .iter()
+                        .filter_map(|planned| match (&planned.owner, planned.placement) {
+                            (Owner::Node(key), Placement::Keep(line))
+                            | (Owner::Node(key), Placement::Before(line))
+                            | (Owner::Node(key), Placement::After(line))
+                                if graph_builder.nodes[key].module == *name =>
+                            {
+                                Some(line)
+                            }
+                            _ => None,
+                        })
+                        .min()
+                        .map(|line| (file.clone(), Placement::Before(line)))
+                });
+                let Some(first) = first else {
+                    skipped.push(format!("Module {} has no annotated nodes to be written next to", name));
+                    continue;
+                };
+                first
+            }
+        };
+        plans.get_mut(&file).unwrap().push(Planned {
+            owner: Owner::Module(name.clone()),
+            annotation,
+            placement,
+            node_line: None,
+        });
+    }
+    plans
+}
+
+fn node_annotation(node: &GraphNode) -> Option<Annotation> {
+    let name = format!("{}::{}", node.module, node.name);
+    let span_name = |suffix: &str| {
+        let name = node.name.strip_suffix(suffix).unwrap_or(&node.name);
+        format!("{}::{}", node.module, name)
+    };
+    Some(match node.node_type.as_str() {
+        "Event" => Annotation::Event {
+            name,
+            capture: node.captures.clone(),
+            when: node.when.clone(),
+        },
+        "Flow" => Annotation::Flow { name },
+        "SpanBegin" => Annotation::SpanBegin {
+            name: span_name(SPAN_BEGIN_SUFFIX),
+        },
+        "SpanEnd" => Annotation::SpanEnd {
+            name: span_name(SPAN_END_SUFFIX),
+        },
+        _ => return None,
+    })
+}
+
+struct Rewrite {
+    text: String,
+    // Line of each planned annotation in text
+    placed: Vec<u32>,
+    // file_lines[n] is where line n of the old text is in text
+    file_lines: Vec<u32>,
+}
+fn rewrite(text: &str, snapshot: &SourceSnapshot, planned: &[Planned], syntax: &(String, String)) -> Rewrite {
+    let old_lines: Vec<&str> = text.lines().collect();
+    let mut out: Vec<String> = Vec::new();
+    let mut placed = vec![0; planned.len()];
+    let mut file_lines = vec![0; old_lines.len() + 2];
+    let mut owned: BTreeSet<u32> = snapshot.annotations.keys().copied().collect();
+    owned.extend(planned.iter().filter_map(|planned| match planned.placement {
+        Placement::Keep(line) => Some(line),
+        _ => None,
+    }));
+
+    // New annotations take the indentation of the line they are next to
+    let insert = |out: &mut Vec<String>, placed: &mut Vec<u32>, placement: Placement, indent: &str| {
+        for (i, planned) in planned.iter().enumerate() {
+            if planned.placement == placement {
+                out.push(annotation_line(indent, syntax, &planned.annotation));
+                placed[i] = out.len() as u32;
+            }
+        }
+    };
+    for n in 1..=old_lines.len() as u32 + 1 {
+        let indent = old_lines.get(n as usize - 1).map(|line| indentation(line)).unwrap_or("");
+        insert(&mut out, &mut placed, Placement::Before(n), indent);
+        file_lines[n as usize] = out.len() as u32 + 1;
+        let Some(line) = old_lines.get(n as usize - 1) else {
+            break;
+        };
+        if owned.contains(&n) {
+            match planned.iter().position(|planned| planned.placement == Placement::Keep(n)) {
+                Some(i) => {
+                    out.push(replace_annotation(line, &planned[i].annotation));
+                    placed[i] = out.len() as u32;
+                }
+                None => out.extend(strip_annotation(line, syntax)),
+            }
+        } else {
+            out.push(line.to_string());
+        }
+        insert(&mut out, &mut placed, Placement::After(n), indent);
+    }
+
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut text_out = out.join(newline);
+    if text.ends_with('\n') && !out.is_empty() {
+        text_out.push_str(newline);
+    }
+    Rewrite {
+        text: text_out,
+        placed,
+        file_lines,
+    }
+}
+
+fn annotation_line(indent: &str, (open, close): &(String, String), annotation: &Annotation) -> String {
+    let mut line = format!("{}{} {}", indent, open, annotation_text(annotation));
+    if !close.is_empty() {
+        line.push(' ');
+        line.push_str(close);
+    }
+    line
+}
+// Keeps the line as it is when the annotation did not change
+fn replace_annotation(line: &str, annotation: &Annotation) -> String {
+    let old = file_parsing::parse_line(line).ok().flatten();
+    let mut annotation = annotation.clone();
+    // Module comments are not shown in the UI
+    if let (Annotation::Module { comment, .. }, Some(Annotation::Module { comment: old_comment, .. })) =
+        (&mut annotation, &old)
+    {
+        *comment = old_comment.clone();
+    }
+    if old.as_ref() == Some(&annotation) {
+        return line.to_string();
+    }
+    let re = Regex::new(ANNOTATION_PATTERN).unwrap();
+    re.replace(line, NoExpand(&annotation_text(&annotation))).into_owned()
+}
+// Removes the annotation and its comment, or the whole line if
+// there is nothing else on it
+fn strip_annotation(line: &str, (open, close): &(String, String)) -> Option<String> {
+    let re = Regex::new(ANNOTATION_PATTERN).unwrap();
+    let Some(found) = re.find(line) else {
+        return Some(line.to_string());
+    };
+    let code = line[..found.start()].trim_end();
+    let code = code.strip_suffix(open.as_str()).unwrap_or(code).trim_end();
+    let rest = line[found.end()..].trim_start();
+    let rest = rest.strip_prefix(close.as_str()).unwrap_or(rest).trim_start();
+    let stripped = match (code.trim().is_empty(), rest.is_empty()) {
+        (true, true) => return None,
+        (_, true) => code.to_string(),
+        (true, false) => format!("{}{}", indentation(line), rest),
+        (false, false) => format!("{} {}", code, rest),
+    };
+    Some(stripped)
+}
+
+// The comment syntax of the annotations already on a line of their
+// own in the file, or else the usual one for the language
+fn comment_syntax<'a>(file: &Path, lines: &[&str], annotation_lines: impl Iterator<Item = &'a u32>) -> (String, String) {
+    let re = Regex::new(ANNOTATION_PATTERN).unwrap();
+    for line in annotation_lines {
+        let Some(text) = lines.get(*line as usize - 1) else {
+            continue;
+        };
+        let Some(found) = re.find(text) else {
+            continue;
+        };
+        let open = text[..found.start()].trim();
+        if !open.is_empty() && !open.contains(char::is_whitespace) {
+            return (open.into(), text[found.end()..].trim().into());
+        }
+    }
+    let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let (open, close) = match extension {
+        "py" | "sh" | "bash" | "rb" | "pl" | "s" | "S" | "cmake" | "nim" | "jl" => ("#", ""),
+        "hs" | "lua" | "adb" | "ads" | "sql" => ("--", ""),
+        "f" | "f90" | "f95" | "f03" | "for" => ("!", ""),
+        "ml" | "mli" | "pas" => ("(*", "*)"),
+        "asm" | "nasm" => (";", ""),
+        // C, C++, Rust, Go, Zig, D, Swift and friends
+        _ => ("//", ""),
+    };
+    (open.into(), close.into())
+}
+
+// {type:"event", name:"module::self"} in the style of the README
+fn annotation_text(annotation: &Annotation) -> String {
+    let quote = |text: &str| serde_json::to_string(text).unwrap();
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    match annotation {
+        Annotation::Module {
+            name,
+            parent_module,
+            comment,
+        } => {
+            fields.push(("type", quote("module")));
+            fields.push(("name", quote(name)));
+            if let Some(parent_module) = parent_module {
+                fields.push(("parent_module", quote(parent_module)));
+            }
+            if let Some(comment) = comment {
+                fields.push(("comment", quote(comment)));
+            }
+        }
+        Annotation::Event {
+            name,
+            capture,
+            when,
+        } => {
+            fields.push(("type", quote("event")));
+            fields.push(("name", quote(name)));
+            if !capture.is_empty() {
+                let capture: Vec<String> = capture.iter().map(|expr| quote(expr)).collect();
+                fields.push(("capture", format!("[{}]", capture.join(", "))));
+            }
+            if let Some(when) = when {
+                fields.push(("when", quote(when)));
+            }
+        }
+        Annotation::Flow { name } => {
+            fields.push(("type", quote("flow")));
+            fields.push(("name", quote(name)));
+        }
+        Annotation::Functions {
+            pattern,
+            file,
+            module,
+        } => {
+            fields.push(("type", quote("functions")));
+            if let Some(pattern) = pattern {
+                fields.push(("match", quote(pattern)));
+            }
+            if let Some(file) = file {
+                fields.push(("file", quote(file)));
+            }
+            if !module.is_empty() {
+                fields.push(("module", quote(module)));
+            }
+        }
+        Annotation::SpanBegin { name } => {
+            fields.push(("type", quote("span_begin")));
+            fields.push(("name", quote(name)));
+        }
+        Annotation::SpanEnd { name } => {
+            fields.push(("type", quote("span_end")));
+            fields.push(("name", quote(name)));
+        }
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", key, value))
+        .collect();
+    format!("[[{{{}}}]]", fields.join(", "))
+}
+
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+fn hash_text(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Asks a running explore server to write its annotations.
+// There is no HTTP client in the dependencies and this is one request.
+pub fn request_write(
+    server: &str,
+    trace_id: TraceID,
+    token: Option<&str>,
+) -> anyhow::Result<WriteAnnotationsResponse> {
+    let body = serde_json::to_string(&WriteAnnotationsRequest { trace_id })?;
+    let authorization = token
+        .map(|token| format!("Authorization: Bearer {}\r\n", token))
+        .unwrap_or_default();
+    let mut stream = TcpStream::connect(server)
+        .map_err(|e| anyhow::anyhow!("Unable to connect to the server at {}: {}", server, e))?;
+    write!(
+        stream,
+        "POST /write_annotations HTTP/1.1\r\nHost: {}\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        server,
+        authorization,
+        body.len(),
+        body
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("Invalid response from the server"))?;
+    let status = head.lines().next().unwrap_or("");
+    if !status.contains(" 200 ") {
+        anyhow::bail!("The server responded with {}: {}", status, body);
+    }
+    Ok(serde_json::from_str(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_structs::{GraphModule, LineLocation};
+    use std::collections::HashMap;
+
+    const SOURCE: &str = "\
+int main() {
+    // [[{type:\"module\", name:\"app\", comment:\"the app\"}]]
+    // [[{type:\"event\", name:\"app::start\"}]]
+    setup();
+    run(); // [[{type:\"event\", name:\"app::run\"}]]
+    return 0;
+}
+";
+    fn node(file: &Path, module: &str, name: &str, line_num: u32, origin: AnnotationOrigin) -> GraphNode {
+        GraphNode {
+            FQN: format!("::{}::{}", module, name),
+            module: module.into(),
+            name: name.into(),
+            address: 0,
+            node_type: "Event".into(),
+            location: LineLocation {
+                file: file.to_path_buf(),
+                line_num,
+                column_num: 0,
+            },
+            labeled_transitions: Vec::new(),
+            node_attributes: HashMap::new(),
+            captures: Vec::new(),
+            when: None,
+            fixed_address: false,
+            origin,
+        }
+    }
+    // The graph builder state right after SOURCE is parsed
+    fn parsed(file: &Path) -> GraphBuilder {
+        let mut graph_builder = GraphBuilder::new(0, std::env::temp_dir());
+        let mut snapshot = SourceSnapshot::new(SOURCE);
+        snapshot.record_annotation(2, None);
+        snapshot.record_annotation(3, Some(4));
+        snapshot.record_annotation(5, Some(5));
+        graph_builder.sources.insert(file.to_path_buf(), snapshot);
+        let origin = |line| AnnotationOrigin::Source {
+            file: file.to_path_buf(),
+            line,
+        };
+        graph_builder.nodes.insert(1, node(file, "app", "start", 4, origin(3)));
+        graph_builder.nodes.insert(2, node(file, "app", "run", 5, origin(5)));
+        graph_builder.modules.insert(
+            "app".into(),
+            GraphModule {
+                name: "app".into(),
+                parent: Some("".into()),
+                module_attributes: HashMap::new(),
+                origin: origin(2),
+            },
+        );
+        std::fs::write(file, SOURCE).unwrap();
+        graph_builder
+    }
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("explorant-{}-{}.c", name, std::process::id()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let annotations = [
+            Annotation::Module {
+                name: "app".into(),
+                parent_module: Some("server".into()),
+                comment: Some("says \"hi\"".into()),
+            },
+            Annotation::Event {
+                name: "app::start".into(),
+                capture: vec!["argc".into(), "argv->x".into()],
+                when: Some("argc > 1".into()),
+            },
+            Annotation::Flow {
+                name: "::branch".into(),
+            },
+            Annotation::Functions {
+                pattern: Some("parse_*".into()),
+                file: None,
+                module: "parser".into(),
+            },
+            Annotation::SpanEnd {
+                name: "app::request".into(),
+            },
+        ];
+        for annotation in annotations {
+            let text = annotation_text(&annotation);
+            assert_eq!(file_parsing::parse_line(&text).unwrap(), Some(annotation));
+        }
+        assert_eq!(
+            annotation_text(&Annotation::Flow { name: "a::b".into() }),
+            r#"[[{type:"flow", name:"a::b"}]]"#
+        );
+    }
+    #[test]
+    fn unchanged() {
+        let file = temp_file("unchanged");
+        let mut graph_builder = parsed(&file);
+        let response = write_annotations(&mut graph_builder);
+        assert!(response.written.is_empty());
+        assert!(response.conflicts.is_empty());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), SOURCE);
+        std::fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn edits() {
+        let file = temp_file("edits");
+        let mut graph_builder = parsed(&file);
+        // Capture argc at start, drop run and add a flow after return
+        graph_builder.nodes.get_mut(&1).unwrap().captures = vec!["argc".into()];
+        graph_builder.nodes.remove(&2);
+        let mut ret = node(&file, "app", "ret", 6, AnnotationOrigin::New);
+        ret.node_type = "Flow".into();
+        graph_builder.nodes.insert(3, ret);
+        let response = write_annotations(&mut graph_builder);
+        assert_eq!(response.written, vec![file.clone()]);
+        let expected = "\
+int main() {
+    // [[{type:\"module\", name:\"app\", comment:\"the app\"}]]
+    // [[{type:\"event\", name:\"app::start\", capture:[\"argc\"]}]]
+    setup();
+    run();
+    return 0;
+    // [[{type:\"flow\", name:\"app::ret\"}]]
+}
+";
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), expected);
+        assert_eq!(
+            graph_builder.nodes[&3].origin,
+            AnnotationOrigin::Source {
+                file: file.clone(),
+                line: 7
+            }
+        );
+
+        // Move start to return 0, which is still line 6 of the binary
+        graph_builder.nodes.get_mut(&1).unwrap().location.line_num = 6;
+        let response = write_annotations(&mut graph_builder);
+        assert!(response.conflicts.is_empty());
+        let text = std::fs::read_to_string(&file).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[2], "    setup();");
+        assert_eq!(lines[4], r#"    // [[{type:"event", name:"app::start", capture:["argc"]}]]"#);
+        assert_eq!(lines[5], "    return 0;");
+
+        // Nothing is written over changes made since
+        std::fs::write(&file, "int main() {}\n").unwrap();
+        graph_builder.nodes.get_mut(&1).unwrap().captures.clear();
+        let response = write_annotations(&mut graph_builder);
+        assert!(response.written.is_empty());
+        assert_eq!(response.conflicts.len(), 1);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "int main() {}\n");
+        std::fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn new_module() {
+        let file = temp_file("new_module");
+        let mut graph_builder = parsed(&file);
+        graph_builder.modules.insert(
+            "setup".into(),
+            GraphModule {
+                name: "setup".into(),
+                parent: Some("app".into()),
+                module_attributes: HashMap::new(),
+                origin: AnnotationOrigin::New,
+            },
+        );
+        graph_builder.modules.insert(
+            "empty".into(),
+            GraphModule {
+                name: "empty".into(),
+                parent: None,
+                module_attributes: HashMap::new(),
+                origin: AnnotationOrigin::New,
+            },
+        );
+        graph_builder.nodes.get_mut(&2).unwrap().module = "setup".into();
+        let response = write_annotations(&mut graph_builder);
+        assert_eq!(response.skipped.len(), 1);
+        let text = std::fs::read_to_string(&file).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[4],
+            r#"    // [[{type:"module", name:"setup", parent_module:"app"}]]"#
+        );
+        assert_eq!(lines[5], r#"    run(); // [[{type:"event", name:"setup::run"}]]"#);
+        std::fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn comments() {
+        let syntax = |file: &str, lines: &[&str]| comment_syntax(Path::new(file), lines, [1].iter());
+        assert_eq!(syntax("a.py", &[""]), ("#".into(), "".into()));
+        assert_eq!(syntax("a.rs", &["x"]), ("//".into(), "".into()));
+        assert_eq!(
+            syntax("a.c", &[r#"  /* [[{type:"flow", name:"::a"}]] */"#]),
+            ("/*".into(), "*/".into())
+        );
+        let c = ("/*".to_string(), "*/".to_string());
+        assert_eq!(strip_annotation(r#"  /* [[{type:"flow", name:"::a"}]] */"#, &c), None);
+        assert_eq!(
+            strip_annotation(r#"  x(); /* [[{type:"flow", name:"::a"}]] */ y();"#, &c),
+            Some("  x(); y();".into())
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use actix_web::cookie::{Cookie, SameSite};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+// With --auth every request needs the token of the server, either as
+//   Authorization: Bearer <token>
+// or as the explorant_token cookie. Opening the launch URL, which has
+// ?token=<token>, sets the cookie so the browser can load the UI.
+pub const TOKEN_COOKIE: &str = "explorant_token";
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Granted,
+    // Granted by the token in the URL, the response sets the cookie
+    GrantedByQuery,
+    Denied,
+}
+
+pub fn new_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect()
+}
+pub fn check(token: &str, authorization: Option<&str>, cookie: Option<&str>, query: &str) -> Access {
+    let bearer = authorization.and_then(|value| value.strip_prefix("Bearer "));
+    if bearer.into_iter().chain(cookie).any(|given| same(given, token)) {
+        return Access::Granted;
+    }
+    // Tokens are alphanumeric so they are never percent encoded
+    let in_query = query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("token="))
+        .any(|given| same(given, token));
+    if in_query {
+        Access::GrantedByQuery
+    } else {
+        Access::Denied
+    }
+}
+pub fn token_cookie(token: &str) -> Cookie<'static> {
+    Cookie::build(TOKEN_COOKIE, token.to_string())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish()
+}
+// Compares every byte so the time taken does not leak how much of
+// a guess was right
+fn same(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let token = new_token();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert!(token.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(token, new_token());
+    }
+    #[test]
+    fn access() {
+        let token = "abc123";
+        assert_eq!(check(token, Some("Bearer abc123"), None, ""), Access::Granted);
+        assert_eq!(check(token, None, Some("abc123"), ""), Access::Granted);
+        assert_eq!(check(token, None, None, "a=1&token=abc123"), Access::GrantedByQuery);
+        assert_eq!(check(token, Some("abc123"), None, ""), Access::Denied);
+        assert_eq!(check(token, Some("Bearer abc12"), Some("abc1234"), ""), Access::Denied);
+        assert_eq!(check(token, None, None, "token=abc124"), Access::Denied);
+        assert_eq!(check(token, None, None, ""), Access::Denied);
+    }
+}
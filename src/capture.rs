@@ -0,0 +1,747 @@
+use gimli::UnwindSection;
+use librr_rs::{BinaryInterface, GdbRegister, GdbThreadId};
+
+use crate::erebor::{BaseEncoding, Erebor, Member, Variable, VariableType};
+
+// Reads the values of `capture` expressions of an event out of the
+// replay when the event is hit.
+//
+// An expression is a variable name followed by any number of
+// `.field` and `->field` accesses: `total`, `head->p.x`.
+// Values are formatted roughly like gdb prints them and every
+// failure becomes a `<...>` string instead of an error so that
+// one bad capture does not stop the recording.
+
+// Nested structs past this depth print as {...}
+const MAX_FORMAT_DEPTH: usize = 2;
+const MAX_ARRAY_ELEMENTS: usize = 32;
+const MAX_STRING_LEN: usize = 200;
+// Only x86_64 is supported
+const ADDRESS_SIZE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Access {
+    // .field
+    Field(String),
+    // ->field
+    Deref(String),
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureExpr {
+    pub variable: String,
+    pub accesses: Vec<Access>,
+}
+
+pub fn parse_capture(expr: &str) -> anyhow::Result<CaptureExpr> {
+    let is_ident = |s: &str| {
+        !s.is_empty()
+            && !s.starts_with(|c: char| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let expr = expr.trim();
+    let end = expr.find(['.', '-']).unwrap_or(expr.len());
+    let variable = &expr[..end];
+    if !is_ident(variable) {
+        anyhow::bail!("Invalid capture `{}`: expected a variable name", expr);
+    }
+    let mut accesses = Vec::new();
+    let mut rest = &expr[end..];
+    while !rest.is_empty() {
+        let (deref, after) = if let Some(after) = rest.strip_prefix("->") {
+            (true, after)
+        } else if let Some(after) = rest.strip_prefix('.') {
+            (false, after)
+        } else {
+            anyhow::bail!("Invalid capture `{}`: expected `.` or `->`", expr);
+        };
+        let end = after.find(['.', '-']).unwrap_or(after.len());
+        let field = &after[..end];
+        if !is_ident(field) {
+            anyhow::bail!("Invalid capture `{}`: expected a field name", expr);
+        }
+        accesses.push(if deref {
+            Access::Deref(field.to_string())
+        } else {
+            Access::Field(field.to_string())
+        });
+        rest = &after[end..];
+    }
+    Ok(CaptureExpr {
+        variable: variable.to_string(),
+        accesses,
+    })
+}
+
+// Registers and memory of the stopped thread
+pub trait TargetAccess {
+    // register is the DWARF register number
+    fn register(&mut self, register: u16) -> anyhow::Result<u64>;
+    fn memory(&mut self, address: u64, size: usize) -> anyhow::Result<Vec<u8>>;
+}
+
+pub struct ReplayAccess<'a> {
+    pub bin_interface: &'a BinaryInterface,
+    pub thread: GdbThreadId,
+}
+impl<'a> TargetAccess for ReplayAccess<'a> {
+    fn register(&mut self, register: u16) -> anyhow::Result<u64> {
+        // x86_64 DWARF register numbers
+        let register = match register {
+            0 => GdbRegister::DREG_RAX,
+            1 => GdbRegister::DREG_RDX,
+            2 => GdbRegister::DREG_RCX,
+            3 => GdbRegister::DREG_RBX,
+            4 => GdbRegister::DREG_RSI,
+            5 => GdbRegister::DREG_RDI,
+            6 => GdbRegister::DREG_RBP,
+            7 => GdbRegister::DREG_RSP,
+            8 => GdbRegister::DREG_R8,
+            9 => GdbRegister::DREG_R9,
+            10 => GdbRegister::DREG_R10,
+            11 => GdbRegister::DREG_R11,
+            12 => GdbRegister::DREG_R12,
+            13 => GdbRegister::DREG_R13,
+            14 => GdbRegister::DREG_R14,
+            15 => GdbRegister::DREG_R15,
+            16 => GdbRegister::DREG_RIP,
+            _ => anyhow::bail!("unsupported DWARF register {}", register),
+        };
+        Ok(self
+            .bin_interface
+            .get_register(register, self.thread)
+            .to_usize() as u64)
+    }
+    fn memory(&mut self, address: u64, size: usize) -> anyhow::Result<Vec<u8>> {
+        let bytes = self.bin_interface.get_mem(address as usize, size);
+        if bytes.len() != size {
+            anyhow::bail!("unable to read {} bytes at {:#x}", size, address);
+        }
+        Ok(bytes)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueLocation {
+    Memory(u64),
+    Register(u16),
+    // The value itself (DW_OP_stack_value)
+    Value(u64),
+    OptimizedOut,
+}
+
+// Never fails, errors are returned as the value
+pub fn capture_value(erebor: &Erebor, target: &mut dyn TargetAccess, pc: usize, expr: &str) -> String {
+    match read_capture(erebor, target, pc, expr) {
+        Ok(value) => value,
+        Err(e) => format!("<error: {}>", e),
+    }
+}
+
+fn read_capture(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    pc: usize,
+    expr: &str,
+) -> anyhow::Result<String> {
+    let expr = parse_capture(expr)?;
+    let (location, type_offset) = locate_capture(erebor, target, pc, &expr)?;
+    format_value(erebor, target, location, type_offset, 0)
+}
+
+// The value of a scalar (integer, char, bool, enum or pointer) capture
+// as a number that conditions can compare
+pub fn read_integer(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    pc: usize,
+    expr: &CaptureExpr,
+) -> anyhow::Result<i64> {
+    let (location, type_offset) = locate_capture(erebor, target, pc, expr)?;
+    if location == ValueLocation::OptimizedOut {
+        anyhow::bail!("{} is optimized out", expr.variable);
+    }
+    Ok(match resolve_type(erebor, type_offset) {
+        Some(VariableType::Base { size, encoding, .. }) => {
+            let bytes = read_bytes(target, location, *size)?;
+            match encoding {
+                BaseEncoding::Signed | BaseEncoding::SignedChar => to_i64(&bytes),
+                BaseEncoding::Unsigned | BaseEncoding::UnsignedChar | BaseEncoding::Bool => {
+                    to_u64(&bytes) as i64
+                }
+                _ => anyhow::bail!("only integer values can be compared"),
+            }
+        }
+        Some(VariableType::Enum { size, .. }) => to_i64(&read_bytes(target, location, *size)?),
+        Some(VariableType::Pointer { .. }) => {
+            to_u64(&read_bytes(target, location, ADDRESS_SIZE)?) as i64
+        }
+        _ => anyhow::bail!("only integer values can be compared"),
+    })
+}
+
+fn locate_capture(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    pc: usize,
+    expr: &CaptureExpr,
+) -> anyhow::Result<(ValueLocation, Option<usize>)> {
+    let (encoding, frame_base, variable) = erebor
+        .find_variable(pc, &expr.variable)
+        .ok_or_else(|| anyhow::anyhow!("no variable {} at {:#x}", expr.variable, pc))?;
+    let mut location = variable_location(erebor, target, pc, encoding, frame_base, variable)?;
+    let mut type_offset = variable.type_offset;
+    for access in &expr.accesses {
+        let field = match access {
+            Access::Field(field) => field,
+            Access::Deref(field) => {
+                let pointer = match resolve_type(erebor, type_offset) {
+                    Some(VariableType::Pointer { target: pointee }) => *pointee,
+                    _ => anyhow::bail!("cannot use -> on a value that is not a pointer"),
+                };
+                let address = to_u64(&read_bytes(target, location, ADDRESS_SIZE)?);
+                if address == 0 {
+                    anyhow::bail!("null pointer before ->{}", field);
+                }
+                location = ValueLocation::Memory(address);
+                type_offset = pointer;
+                field
+            }
+        };
+        let members = match resolve_type(erebor, type_offset) {
+            Some(VariableType::Struct { members, .. }) => members,
+            _ => anyhow::bail!("cannot access .{} on a value that is not a struct", field),
+        };
+        let member = members
+            .iter()
+            .find(|member| member.name == *field)
+            .ok_or_else(|| anyhow::anyhow!("no field {}", field))?;
+        location = match location {
+            ValueLocation::Memory(address) => {
+                ValueLocation::Memory(member_address(address, member)?)
+            }
+            _ => anyhow::bail!("cannot access .{} of a struct that is not in memory", field),
+        };
+        type_offset = member.type_offset;
+    }
+    Ok((location, type_offset))
+}
+
+fn variable_location(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    pc: usize,
+    encoding: gimli::Encoding,
+    frame_base: Option<&Vec<u8>>,
+    variable: &Variable,
+) -> anyhow::Result<ValueLocation> {
+    let dwarf_pc = pc.wrapping_sub(erebor.address_offset);
+    let Some(expr) = variable.location_at(dwarf_pc) else {
+        return Ok(ValueLocation::OptimizedOut);
+    };
+    evaluate(erebor, target, pc, encoding, frame_base, expr)
+}
+
+fn evaluate(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    pc: usize,
+    encoding: gimli::Encoding,
+    frame_base: Option<&Vec<u8>>,
+    expr: &[u8],
+) -> anyhow::Result<ValueLocation> {
+    let expr = gimli::Expression(gimli::EndianSlice::new(expr, gimli::RunTimeEndian::Little));
+    let mut evaluation = expr.evaluation(encoding);
+    let mut result = evaluation.evaluate()?;
+    loop {
+        result = match result {
+            gimli::EvaluationResult::Complete => break,
+            gimli::EvaluationResult::RequiresRegister { register, .. } => {
+                let value = target.register(register.0)?;
+                evaluation.resume_with_register(gimli::Value::Generic(value))?
+            }
+            gimli::EvaluationResult::RequiresMemory { address, size, .. } => {
+                let bytes = target.memory(address, size as usize)?;
+                evaluation.resume_with_memory(gimli::Value::Generic(to_u64(&bytes)))?
+            }
+            gimli::EvaluationResult::RequiresFrameBase => {
+                let frame_base = frame_base
+                    .ok_or_else(|| anyhow::anyhow!("the function has no frame base"))?;
+                let address = match evaluate(erebor, target, pc, encoding, None, frame_base)? {
+                    ValueLocation::Memory(address) | ValueLocation::Value(address) => address,
+                    ValueLocation::Register(register) => target.register(register)?,
+                    ValueLocation::OptimizedOut => anyhow::bail!("the frame base is unavailable"),
+                };
+                evaluation.resume_with_frame_base(address)?
+            }
+            gimli::EvaluationResult::RequiresCallFrameCfa => {
+                let cfa = call_frame_cfa(erebor, target, pc, encoding)?;
+                evaluation.resume_with_call_frame_cfa(cfa)?
+            }
+            gimli::EvaluationResult::RequiresRelocatedAddress(address) => {
+                evaluation.resume_with_relocated_address(address + erebor.address_offset as u64)?
+            }
+            other => anyhow::bail!("unsupported DWARF location ({:?})", other),
+        };
+    }
+    let pieces = evaluation.result();
+    if pieces.len() != 1 {
+        anyhow::bail!("values split over several locations are not supported");
+    }
+    Ok(match pieces[0].location {
+        gimli::Location::Empty => ValueLocation::OptimizedOut,
+        gimli::Location::Register { register } => ValueLocation::Register(register.0),
+        gimli::Location::Address { address } => ValueLocation::Memory(address),
+        gimli::Location::Value { value } => ValueLocation::Value(value.to_u64(!0)?),
+        _ => anyhow::bail!("unsupported DWARF location"),
+    })
+}
+
+// The CFA of the frame at pc, from the unwind rules in .eh_frame or
+// .debug_frame. There is no guess for code without any.
+fn call_frame_cfa(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    pc: usize,
+    encoding: gimli::Encoding,
+) -> anyhow::Result<u64> {
+    let dwarf_pc = pc.wrapping_sub(erebor.address_offset) as u64;
+    let call_frames = &erebor.call_frames;
+    let endian = gimli::RunTimeEndian::Little;
+    let bases = gimli::BaseAddresses::default().set_eh_frame(call_frames.eh_frame_address);
+    let mut context = gimli::UnwindContext::new();
+    let eh_frame = gimli::EhFrame::new(&call_frames.eh_frame, endian);
+    let mut debug_frame = gimli::DebugFrame::new(&call_frames.debug_frame, endian);
+    debug_frame.set_address_size(ADDRESS_SIZE as u8);
+    let cfa = match eh_frame.unwind_info_for_address(
+        &bases,
+        &mut context,
+        dwarf_pc,
+        gimli::EhFrame::cie_from_offset,
+    ) {
+        Ok(row) => row.cfa().clone(),
+        Err(_) => debug_frame
+            .unwind_info_for_address(
+                &bases,
+                &mut context,
+                dwarf_pc,
+                gimli::DebugFrame::cie_from_offset,
+            )
+            .map_err(|_| anyhow::anyhow!("no call frame information at {:#x}", pc))?
+            .cfa()
+            .clone(),
+    };
+    Ok(match cfa {
+        gimli::CfaRule::RegisterAndOffset { register, offset } => {
+            target.register(register.0)?.wrapping_add(offset as u64)
+        }
+        gimli::CfaRule::Expression(expr) => {
+            match evaluate(erebor, target, pc, encoding, None, expr.0.slice())? {
+                ValueLocation::Memory(address) | ValueLocation::Value(address) => address,
+                _ => anyhow::bail!("unsupported CFA expression at {:#x}", pc),
+            }
+        }
+    })
+}
+
+fn member_address(struct_address: u64, member: &Member) -> anyhow::Result<u64> {
+    let offset = member
+        .offset
+        .ok_or_else(|| anyhow::anyhow!("the location of field {} is not supported", member.name))?;
+    Ok(struct_address + offset as u64)
+}
+
+// Follows typedefs and qualifiers
+fn resolve_type(erebor: &Erebor, mut type_offset: Option<usize>) -> Option<&VariableType> {
+    // Bounded in case of a cycle in broken DWARF
+    for _ in 0..32 {
+        match erebor.types.get(&type_offset?)? {
+            VariableType::Alias { target } => type_offset = *target,
+            ty => return Some(ty),
+        }
+    }
+    None
+}
+
+fn type_size(erebor: &Erebor, type_offset: Option<usize>) -> Option<usize> {
+    match resolve_type(erebor, type_offset)? {
+        VariableType::Base { size, .. } => Some(*size),
+        VariableType::Pointer { .. } => Some(ADDRESS_SIZE),
+        VariableType::Struct { size, .. } => Some(*size),
+        VariableType::Enum { size, .. } => Some(*size),
+        VariableType::Array { element, count } => Some(type_size(erebor, *element)? * (*count)?),
+        VariableType::Alias { .. } => None,
+    }
+}
+
+fn read_bytes(
+    target: &mut dyn TargetAccess,
+    location: ValueLocation,
+    size: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = match location {
+        ValueLocation::Memory(address) => return target.memory(address, size),
+        ValueLocation::Register(register) => target.register(register)?.to_le_bytes().to_vec(),
+        ValueLocation::Value(value) => value.to_le_bytes().to_vec(),
+        ValueLocation::OptimizedOut => anyhow::bail!("optimized out"),
+    };
+    if size > bytes.len() {
+        anyhow::bail!("a {} byte value does not fit in a register", size);
+    }
+    bytes.truncate(size);
+    Ok(bytes)
+}
+
+fn to_u64(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(buf)
+}
+
+fn to_i64(bytes: &[u8]) -> i64 {
+    let len = bytes.len().min(8);
+    if len == 0 {
+        return 0;
+    }
+    let shift = 64 - 8 * len as u32;
+    ((to_u64(bytes) << shift) as i64) >> shift
+}
+
+fn format_value(
+    erebor: &Erebor,
+    target: &mut dyn TargetAccess,
+    location: ValueLocation,
+    type_offset: Option<usize>,
+    depth: usize,
+) -> anyhow::Result<String> {
+    if location == ValueLocation::OptimizedOut {
+        return Ok("<optimized out>".into());
+    }
+    let Some(ty) = resolve_type(erebor, type_offset) else {
+        let bytes = read_bytes(target, location, ADDRESS_SIZE)?;
+        return Ok(format!("{:#x}", to_u64(&bytes)));
+    };
+    Ok(match ty {
+        VariableType::Base { size, encoding, .. } => {
+            let bytes = read_bytes(target, location, *size)?;
+            format_base(&bytes, *encoding)
+        }
+        VariableType::Enum { size, values } => {
+            let value = to_i64(&read_bytes(target, location, *size)?);
+            match values.iter().find(|(v, _)| *v == value) {
+                Some((_, name)) => name.clone(),
+                None => value.to_string(),
+            }
+        }
+        VariableType::Pointer { target: pointee } => {
+            let address = to_u64(&read_bytes(target, location, ADDRESS_SIZE)?);
+            let is_char = matches!(
+                resolve_type(erebor, *pointee),
+                Some(VariableType::Base {
+                    encoding: BaseEncoding::SignedChar | BaseEncoding::UnsignedChar,
+                    ..
+                })
+            );
+            match is_char && address != 0 {
+                true => match read_c_string(target, address) {
+                    Some(s) => format!("{:#x} {:?}", address, s),
+                    None => format!("{:#x}", address),
+                },
+                false => format!("{:#x}", address),
+            }
+        }
+        VariableType::Struct { members, .. } => {
+            let ValueLocation::Memory(address) = location else {
+                anyhow::bail!("struct is not in memory");
+            };
+            if depth >= MAX_FORMAT_DEPTH {
+                return Ok("{...}".into());
+            }
+            let mut fields = Vec::new();
+            for member in members {
+                let value = member_address(address, member)
+                    .and_then(|address| {
+                        format_value(
+                            erebor,
+                            target,
+                            ValueLocation::Memory(address),
+                            member.type_offset,
+                            depth + 1,
+                        )
+                    })
+                    .unwrap_or_else(|e| format!("<error: {}>", e));
+                fields.push(format!("{} = {}", member.name, value));
+            }
+            format!("{{{}}}", fields.join(", "))
+        }
+        VariableType::Array { element, count } => {
+            let ValueLocation::Memory(address) = location else {
+                anyhow::bail!("array is not in memory");
+            };
+            let element_size = type_size(erebor, *element)
+                .ok_or_else(|| anyhow::anyhow!("array element has an unknown size"))?;
+            let count = count.unwrap_or(0);
+            let mut elements = Vec::new();
+            for i in 0..count.min(MAX_ARRAY_ELEMENTS) {
+                elements.push(format_value(
+                    erebor,
+                    target,
+                    ValueLocation::Memory(address + (i * element_size) as u64),
+                    *element,
+                    depth + 1,
+                )?);
+            }
+            if count > MAX_ARRAY_ELEMENTS {
+                elements.push("...".into());
+            }
+            format!("{{{}}}", elements.join(", "))
+        }
+        VariableType::Alias { .. } => unreachable!("resolve_type never returns an alias"),
+    })
+}
+
+fn format_base(bytes: &[u8], encoding: BaseEncoding) -> String {
+    match encoding {
+        BaseEncoding::Signed => to_i64(bytes).to_string(),
+        BaseEncoding::Unsigned => to_u64(bytes).to_string(),
+        BaseEncoding::Bool => (to_u64(bytes) != 0).to_string(),
+        BaseEncoding::Float if bytes.len() == 4 => {
+            f32::from_le_bytes(bytes.try_into().unwrap()).to_string()
+        }
+        BaseEncoding::Float if bytes.len() == 8 => {
+            f64::from_le_bytes(bytes.try_into().unwrap()).to_string()
+        }
+        BaseEncoding::SignedChar | BaseEncoding::UnsignedChar => {
+            let value = to_u64(bytes) as u8;
+            let number = match encoding {
+                BaseEncoding::SignedChar => (value as i8).to_string(),
+                _ => value.to_string(),
+            };
+            format!("{} {:?}", number, value as char)
+        }
+        _ => format!("{:#x}", to_u64(bytes)),
+    }
+}
+
+fn read_c_string(target: &mut dyn TargetAccess, address: u64) -> Option<String> {
+    let bytes = target.memory(address, MAX_STRING_LEN).ok()?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::erebor::VariableScope;
+    use gimli::write::{
+        Address, CallFrameInstruction, CommonInformationEntry, DebugFrame, EndianVec,
+        FrameDescriptionEntry, FrameTable,
+    };
+    use std::collections::HashMap;
+
+    const INT: usize = 1;
+    const POINT: usize = 2;
+    const POINT_PTR: usize = 3;
+
+    pub(crate) struct FakeTarget {
+        pub(crate) rbp: u64,
+        pub(crate) rsp: u64,
+        memory: HashMap<u64, u8>,
+    }
+    impl FakeTarget {
+        fn write(&mut self, address: u64, bytes: &[u8]) {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.memory.insert(address + i as u64, *byte);
+            }
+        }
+    }
+    impl TargetAccess for FakeTarget {
+        fn register(&mut self, register: u16) -> anyhow::Result<u64> {
+            match register {
+                6 => Ok(self.rbp),
+                7 => Ok(self.rsp),
+                _ => anyhow::bail!("no register {}", register),
+            }
+        }
+        fn memory(&mut self, address: u64, size: usize) -> anyhow::Result<Vec<u8>> {
+            (address..address + size as u64)
+                .map(|a| {
+                    self.memory
+                        .get(&a)
+                        .copied()
+                        .ok_or_else(|| anyhow::anyhow!("bad read at {:#x}", a))
+                })
+                .collect()
+        }
+    }
+    // The unwind rules of a gcc -O0 function at 0x1000..0x1100:
+    // push %rbp; mov %rsp,%rbp; ...
+    fn call_frames() -> Vec<u8> {
+        let encoding = gimli::Encoding {
+            address_size: 8,
+            format: gimli::Format::Dwarf32,
+            version: 1,
+        };
+        let mut cie = CommonInformationEntry::new(encoding, 1, -8, gimli::X86_64::RA);
+        cie.add_instruction(CallFrameInstruction::Cfa(gimli::X86_64::RSP, 8));
+        let mut fde = FrameDescriptionEntry::new(Address::Constant(0x1000), 0x100);
+        fde.add_instruction(1, CallFrameInstruction::CfaOffset(16));
+        fde.add_instruction(4, CallFrameInstruction::CfaRegister(gimli::X86_64::RBP));
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(cie);
+        table.add_fde(cie, fde);
+        let mut debug_frame = DebugFrame(EndianVec::new(gimli::LittleEndian));
+        table.write_debug_frame(&mut debug_frame).unwrap();
+        debug_frame.0.into_vec()
+    }
+    // int f(struct point *p) { int total; ... } at 0x1000..0x1100
+    // with gcc -O0 locations: p at fbreg -40, total at fbreg -20
+    pub(crate) fn setup() -> (Erebor, FakeTarget) {
+        let mut erebor = Erebor::default();
+        erebor.types.insert(
+            INT,
+            VariableType::Base {
+                name: "int".into(),
+                size: 4,
+                encoding: BaseEncoding::Signed,
+            },
+        );
+        erebor.types.insert(
+            POINT,
+            VariableType::Struct {
+                name: "point".into(),
+                size: 8,
+                members: vec![
+                    Member {
+                        name: "x".into(),
+                        offset: Some(0),
+                        type_offset: Some(INT),
+                    },
+                    Member {
+                        name: "y".into(),
+                        offset: Some(4),
+                        type_offset: Some(INT),
+                    },
+                ],
+            },
+        );
+        erebor
+            .types
+            .insert(POINT_PTR, VariableType::Pointer { target: Some(POINT) });
+        let variable = |name: &str, type_offset: usize, expr: Vec<u8>| {
+            (
+                None,
+                Variable {
+                    name: name.into(),
+                    type_offset: Some(type_offset),
+                    locations: vec![(0, usize::MAX, expr)],
+                },
+            )
+        };
+        erebor.scopes.push(VariableScope {
+            low_pc: 0x1000,
+            high_pc: 0x1100,
+            encoding: gimli::Encoding {
+                address_size: 8,
+                format: gimli::Format::Dwarf32,
+                version: 5,
+            },
+            // DW_OP_call_frame_cfa
+            frame_base: Some(vec![0x9c]),
+            variables: vec![
+                // DW_OP_fbreg -40
+                variable("p", POINT_PTR, vec![0x91, 0x58]),
+                // DW_OP_fbreg -20
+                variable("total", INT, vec![0x91, 0x6c]),
+            ],
+        });
+        erebor.call_frames.debug_frame = call_frames();
+        let mut target = FakeTarget {
+            rbp: 0x7000,
+            rsp: 0x6fe0,
+            memory: HashMap::new(),
+        };
+        // cfa = rbp + 16 after the prologue
+        target.write(0x7010 - 40, &0x2000u64.to_le_bytes());
+        target.write(0x7010 - 20, &(-3i32).to_le_bytes());
+        target.write(0x2000, &5i32.to_le_bytes());
+        target.write(0x2004, &6i32.to_le_bytes());
+        (erebor, target)
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_capture("ptr->field.x").unwrap(),
+            CaptureExpr {
+                variable: "ptr".into(),
+                accesses: vec![Access::Deref("field".into()), Access::Field("x".into())],
+            }
+        );
+        assert!(parse_capture("").is_err());
+        assert!(parse_capture("*ptr").is_err());
+        assert!(parse_capture("a->").is_err());
+        assert!(parse_capture("a-b").is_err());
+    }
+    #[test]
+    fn locals_and_fields() {
+        let (erebor, mut target) = setup();
+        assert_eq!(capture_value(&erebor, &mut target, 0x1010, "total"), "-3");
+        assert_eq!(capture_value(&erebor, &mut target, 0x1010, "p->y"), "6");
+        assert_eq!(capture_value(&erebor, &mut target, 0x1010, "p"), "0x2000");
+        assert_eq!(
+            capture_value(&erebor, &mut target, 0x1010, "p->x").parse::<i32>().unwrap(),
+            5
+        );
+    }
+    #[test]
+    fn integers() {
+        let (erebor, mut target) = setup();
+        let read = |target: &mut FakeTarget, expr: &str| {
+            read_integer(&erebor, target, 0x1010, &parse_capture(expr).unwrap())
+        };
+        assert_eq!(read(&mut target, "total").unwrap(), -3);
+        assert_eq!(read(&mut target, "p").unwrap(), 0x2000);
+        assert_eq!(read(&mut target, "p->y").unwrap(), 6);
+        // null p
+        target.write(0x7010 - 40, &0u64.to_le_bytes());
+        assert!(read(&mut target, "p->x").is_err());
+    }
+    #[test]
+    fn errors_are_values() {
+        let (erebor, mut target) = setup();
+        assert!(capture_value(&erebor, &mut target, 0x1010, "missing").starts_with("<error"));
+        assert!(capture_value(&erebor, &mut target, 0x1010, "total.x").starts_with("<error"));
+        // outside of the function
+        assert!(capture_value(&erebor, &mut target, 0x2000, "total").starts_with("<error"));
+    }
+    #[test]
+    fn unsupported_member_location() {
+        let (mut erebor, mut target) = setup();
+        if let Some(VariableType::Struct { members, .. }) = erebor.types.get_mut(&POINT) {
+            members[1].offset = None;
+        }
+        assert_eq!(
+            capture_value(&erebor, &mut target, 0x1010, "p->y"),
+            "<error: the location of field y is not supported>"
+        );
+        assert_eq!(capture_value(&erebor, &mut target, 0x1010, "p->x"), "5");
+    }
+    #[test]
+    fn function_entry() {
+        let (mut erebor, mut target) = setup();
+        // At the entry the CFA is rsp + 8, whatever is in rbp
+        target.rbp = 0x9000;
+        target.rsp = 0x7008;
+        assert_eq!(capture_value(&erebor, &mut target, 0x1000, "total"), "-3");
+        // After push %rbp
+        target.rsp = 0x7000;
+        assert_eq!(capture_value(&erebor, &mut target, 0x1001, "total"), "-3");
+        // No unwind rules
+        erebor.call_frames = Default::default();
+        assert_eq!(
+            capture_value(&erebor, &mut target, 0x1010, "total"),
+            "<error: no call frame information at 0x1010>"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use crate::capture::{self, CaptureExpr, TargetAccess};
+use crate::erebor::Erebor;
+
+// `when` conditions of an event, checked each time the event is hit.
+//
+// A condition compares integers, registers and capture expressions:
+//   size > 4096
+//   node->next == 0 || $rdi != 0
+//   !(count >= 10 && count < 20)
+// An operand on its own is true when it is not zero.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Integer(i64),
+    // DWARF register number, written as $rax
+    Register(u16),
+    Variable(CaptureExpr),
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, Comparison, Operand),
+    NonZero(Operand),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn evaluate(
+        &self,
+        erebor: &Erebor,
+        target: &mut dyn TargetAccess,
+        pc: usize,
+    ) -> anyhow::Result<bool> {
+        Ok(match self {
+            Condition::Compare(lhs, comparison, rhs) => {
+                let lhs = lhs.evaluate(erebor, target, pc)?;
+                let rhs = rhs.evaluate(erebor, target, pc)?;
+                match comparison {
+                    Comparison::Eq => lhs == rhs,
+                    Comparison::Ne => lhs != rhs,
+                    Comparison::Lt => lhs < rhs,
+                    Comparison::Le => lhs <= rhs,
+                    Comparison::Gt => lhs > rhs,
+                    Comparison::Ge => lhs >= rhs,
+                }
+            }
+            Condition::NonZero(operand) => operand.evaluate(erebor, target, pc)? != 0,
+            Condition::Not(condition) => !condition.evaluate(erebor, target, pc)?,
+            Condition::And(lhs, rhs) => {
+                lhs.evaluate(erebor, target, pc)? && rhs.evaluate(erebor, target, pc)?
+            }
+            Condition::Or(lhs, rhs) => {
+                lhs.evaluate(erebor, target, pc)? || rhs.evaluate(erebor, target, pc)?
+            }
+        })
+    }
+}
+
+impl Operand {
+    fn evaluate(
+        &self,
+        erebor: &Erebor,
+        target: &mut dyn TargetAccess,
+        pc: usize,
+    ) -> anyhow::Result<i64> {
+        match self {
+            Operand::Integer(value) => Ok(*value),
+            Operand::Register(register) => Ok(target.register(*register)? as i64),
+            Operand::Variable(expr) => capture::read_integer(erebor, target, pc, expr),
+        }
+    }
+}
+
+fn register_number(name: &str) -> Option<u16> {
+    // x86_64 DWARF register numbers
+    Some(match name {
+        "rax" => 0,
+        "rdx" => 1,
+        "rcx" => 2,
+        "rbx" => 3,
+        "rsi" => 4,
+        "rdi" => 5,
+        "rbp" => 6,
+        "rsp" => 7,
+        "r8" => 8,
+        "r9" => 9,
+        "r10" => 10,
+        "r11" => 11,
+        "r12" => 12,
+        "r13" => 13,
+        "r14" => 14,
+        "r15" => 15,
+        "rip" => 16,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Integer(i64),
+    Register(String),
+    // A capture expression such as a->b.c
+    Variable(String),
+    Compare(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(condition: &str) -> anyhow::Result<Vec<Token>> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let chars: Vec<char> = condition.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let take_while = |mut end: usize, pred: &dyn Fn(char) -> bool| {
+            while end < chars.len() && pred(chars[end]) {
+                end += 1;
+            }
+            end
+        };
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // A minus is only ever part of a number since there is no arithmetic
+        let negative = c == '-' && next.is_some_and(|n| n.is_ascii_digit());
+        if c.is_ascii_digit() || negative {
+            let end = take_while(i + 1, &is_ident);
+            let text: String = chars[i..end].iter().collect();
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(digits) => (-1, digits),
+                None => (1, text.as_str()),
+            };
+            let value = match digits.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => digits.parse(),
+            }
+            .map_err(|_| anyhow::anyhow!("Invalid number `{}`", text))?;
+            tokens.push(Token::Integer(sign * value));
+            i = end;
+            continue;
+        }
+        if c == '$' {
+            let end = take_while(i + 1, &is_ident);
+            tokens.push(Token::Register(chars[i + 1..end].iter().collect()));
+            i = end;
+            continue;
+        }
+        if is_ident(c) {
+            let mut end = take_while(i, &is_ident);
+            // Keep the field accesses, but not a `-` or `>` comparison after them
+            loop {
+                let rest = &chars[end..];
+                let skip = match rest {
+                    ['.', ..] => 1,
+                    ['-', '>', ..] => 2,
+                    _ => break,
+                };
+                end = take_while(end + skip, &is_ident);
+            }
+            tokens.push(Token::Variable(chars[i..end].iter().collect()));
+            i = end;
+            continue;
+        }
+        let (token, len) = match (c, next) {
+            ('=', Some('=')) => (Token::Compare(Comparison::Eq), 2),
+            ('!', Some('=')) => (Token::Compare(Comparison::Ne), 2),
+            ('<', Some('=')) => (Token::Compare(Comparison::Le), 2),
+            ('>', Some('=')) => (Token::Compare(Comparison::Ge), 2),
+            ('<', _) => (Token::Compare(Comparison::Lt), 1),
+            ('>', _) => (Token::Compare(Comparison::Gt), 1),
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            _ => anyhow::bail!("Unexpected `{}` in condition `{}`", c, condition),
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+// or := and ('||' and)*
+// and := unary ('&&' unary)*
+// unary := '!' unary | '(' or ')' | operand (comparison operand)?
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    fn or(&mut self) -> anyhow::Result<Condition> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            lhs = Condition::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+    fn and(&mut self) -> anyhow::Result<Condition> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            lhs = Condition::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+    fn unary(&mut self) -> anyhow::Result<Condition> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                Ok(Condition::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.next();
+                let condition = self.or()?;
+                if self.next() != Some(Token::Close) {
+                    anyhow::bail!("Expected `)`");
+                }
+                Ok(condition)
+            }
+            _ => {
+                let lhs = self.operand()?;
+                match self.peek() {
+                    Some(Token::Compare(comparison)) => {
+                        let comparison = *comparison;
+                        self.next();
+                        Ok(Condition::Compare(lhs, comparison, self.operand()?))
+                    }
+                    _ => Ok(Condition::NonZero(lhs)),
+                }
+            }
+        }
+    }
+    fn operand(&mut self) -> anyhow::Result<Operand> {
+        Ok(match self.next() {
+            Some(Token::Integer(value)) => Operand::Integer(value),
+            Some(Token::Register(name)) => Operand::Register(
+                register_number(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown register ${}", name))?,
+            ),
+            Some(Token::Variable(expr)) => Operand::Variable(capture::parse_capture(&expr)?),
+            Some(token) => anyhow::bail!("Expected a value, found {:?}", token),
+            None => anyhow::bail!("Expected a value at the end"),
+        })
+    }
+}
+
+pub fn parse_condition(condition: &str) -> anyhow::Result<Condition> {
+    let mut parser = Parser {
+        tokens: tokenize(condition)?,
+        position: 0,
+    };
+    let parsed = parser
+        .or()
+        .map_err(|e| anyhow::anyhow!("Invalid condition `{}`: {}", condition, e))?;
+    if parser.position < parser.tokens.len() {
+        anyhow::bail!(
+            "Invalid condition `{}`: unexpected {:?}",
+            condition,
+            parser.tokens[parser.position]
+        );
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Registers;
+    impl TargetAccess for Registers {
+        fn register(&mut self, register: u16) -> anyhow::Result<u64> {
+            Ok(register as u64 * 100)
+        }
+        fn memory(&mut self, address: u64, _size: usize) -> anyhow::Result<Vec<u8>> {
+            anyhow::bail!("bad read at {:#x}", address)
+        }
+    }
+    fn check(condition: &str) -> anyhow::Result<bool> {
+        parse_condition(condition)?.evaluate(&Erebor::default(), &mut Registers, 0x1000)
+    }
+
+    #[test]
+    fn parse() {
+        let variable = |expr: &str| Operand::Variable(capture::parse_capture(expr).unwrap());
+        assert_eq!(
+            parse_condition("size > 4096").unwrap(),
+            Condition::Compare(variable("size"), Comparison::Gt, Operand::Integer(4096))
+        );
+        assert_eq!(
+            parse_condition("a->b>-1").unwrap(),
+            Condition::Compare(variable("a->b"), Comparison::Gt, Operand::Integer(-1))
+        );
+        assert_eq!(
+            parse_condition("!p || $rdi").unwrap(),
+            Condition::Or(
+                Box::new(Condition::Not(Box::new(Condition::NonZero(variable("p"))))),
+                Box::new(Condition::NonZero(Operand::Register(5))),
+            )
+        );
+        assert!(parse_condition("").is_err());
+        assert!(parse_condition("a >").is_err());
+        assert!(parse_condition("(a == 1").is_err());
+        assert!(parse_condition("a == 1 b").is_err());
+        assert!(parse_condition("$xyz == 1").is_err());
+        assert!(parse_condition("a = 1").is_err());
+    }
+    #[test]
+    fn evaluate() {
+        // $rdi is 500 and $rax is 0
+        assert!(check("$rdi == 500").unwrap());
+        assert!(check("$rdi >= 0x1f4 && $rdi < 501").unwrap());
+        assert!(!check("$rax").unwrap());
+        assert!(!check("$rax || !($rdi == 500)").unwrap());
+        assert!(check("1 < 2 && (0 || -1)").unwrap());
+        // The right side is never read
+        assert!(check("0 && missing").is_ok());
+        assert!(check("missing > 1").is_err());
+    }
+    #[test]
+    fn function_entry() {
+        // Conditions on events at a function's address, before its
+        // prologue has set up rbp
+        let (erebor, mut target) = capture::tests::setup();
+        target.rbp = 0x9000;
+        target.rsp = 0x7008;
+        let check = |target: &mut capture::tests::FakeTarget, condition: &str| {
+            parse_condition(condition)?.evaluate(&erebor, target, 0x1000)
+        };
+        assert!(check(&mut target, "total < 0 && p->y == 6").unwrap());
+        assert!(!check(&mut target, "total > 4096").unwrap());
+    }
+}
@@ -0,0 +1,102 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// Per project settings, read from explorant.json5 in the directory
+// explorant is started from or in the recording:
+//   {
+//       substitute_path: [{from:"/ci/build", to:"/home/me/project"}],
+//   }
+pub const CONFIG_FILE_NAME: &str = "explorant.json5";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub substitute_path: Vec<SubstitutePath>,
+}
+impl ProjectConfig {
+    pub fn load(recording_dir: &Path) -> anyhow::Result<Self> {
+        let path = std::env::current_dir()
+            .ok()
+            .into_iter()
+            .chain(std::iter::once(recording_dir.to_path_buf()))
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|path| path.exists());
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        log::info!("reading config {}", path.to_string_lossy());
+        let text = std::fs::read_to_string(&path)?;
+        json5::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.to_string_lossy(), e))
+    }
+}
+
+// Like gdb's `set substitute-path`: source paths starting with from
+// are read from to instead. Only whole path components match, so
+// /ci/build does not match /ci/build2.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SubstitutePath {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+impl SubstitutePath {
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let (from, to) = rule
+            .split_once('=')
+            .ok_or_else(|| format!("invalid FROM=TO: no `=` found in `{}`", rule))?;
+        if from.is_empty() {
+            return Err(format!("invalid FROM=TO: empty FROM in `{}`", rule));
+        }
+        Ok(Self {
+            from: from.into(),
+            to: to.into(),
+        })
+    }
+}
+// The first rule that matches wins, as in gdb
+pub fn substitute_path(rules: &[SubstitutePath], path: &Path) -> Option<PathBuf> {
+    rules.iter().find_map(|rule| {
+        let rest = path.strip_prefix(&rule.from).ok()?;
+        Some(rule.to.join(rest))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let rules = vec![
+            SubstitutePath::parse("/ci/build/vendor=/opt/vendor").unwrap(),
+            SubstitutePath::parse("/ci/build=/home/me/project").unwrap(),
+        ];
+        let substitute = |path: &str| substitute_path(&rules, Path::new(path));
+        assert_eq!(
+            substitute("/ci/build/src/main.c"),
+            Some(PathBuf::from("/home/me/project/src/main.c"))
+        );
+        assert_eq!(
+            substitute("/ci/build/vendor/zlib/inflate.c"),
+            Some(PathBuf::from("/opt/vendor/zlib/inflate.c"))
+        );
+        assert_eq!(substitute("/ci/build2/main.c"), None);
+        assert_eq!(substitute("/usr/include/stdio.h"), None);
+        assert!(SubstitutePath::parse("/ci/build").is_err());
+        assert!(SubstitutePath::parse("=/home").is_err());
+    }
+    #[test]
+    fn config() {
+        let config: ProjectConfig =
+            json5::from_str(r#"{substitute_path: [{from:"/ci", to:"/home/me"}]}"#).unwrap();
+        assert_eq!(
+            config.substitute_path,
+            vec![SubstitutePath {
+                from: "/ci".into(),
+                to: "/home/me".into()
+            }]
+        );
+        let config: ProjectConfig = json5::from_str("{}").unwrap();
+        assert!(config.substitute_path.is_empty());
+    }
+}
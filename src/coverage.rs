@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::config::SubstitutePath;
+use crate::erebor::Erebor;
+use crate::jobs::JobProgress;
+use crate::shared_structs::{Coverage, FileCoverage, Function};
+use crate::simulation::Simulation;
+use crate::trampoline_replay::{self, Executed};
+
+// Which source lines a recording ran, from a replay of the whole
+// recording with trampolines. A line counts the runs of every block
+// of code it is part of, see trampoline_replay::blocks. Code after the
+// last instrumented instruction of a function is in no block and is
+// left out, like lcov leaves out lines without code.
+
+pub fn measure(simulation: &Simulation, progress: &JobProgress) -> anyhow::Result<Coverage> {
+    let executed = trampoline_replay::record_executed(&simulation.save_directory, None, progress)?;
+    let erebor = simulation
+        .dwarf_data
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    Ok(line_coverage(&erebor, &executed))
+}
+
+pub fn line_coverage(erebor: &Erebor, executed: &Executed) -> Coverage {
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    for addr in &executed.addresses {
+        *counts.entry(*addr).or_insert(0) += 1;
+    }
+    let block_ends: BTreeSet<usize> = executed
+        .instrumented
+        .iter()
+        .chain(counts.keys())
+        .copied()
+        .collect();
+    // Code outside the functions of the binary, such as in shared
+    // libraries, has no line table. Functions from headers can be
+    // listed by every file that includes them.
+    let functions: BTreeMap<usize, &Function> = erebor
+        .files
+        .values()
+        .flat_map(|file| &file.functions)
+        .map(|function| (function.address, function))
+        .collect();
+    let mut files: BTreeMap<PathBuf, BTreeMap<u32, usize>> = BTreeMap::new();
+    for function in functions.values() {
+        for (block, count) in trampoline_replay::blocks(function, &block_ends, &counts) {
+            let dwarf_block = block.start().wrapping_sub(erebor.address_offset)
+                ..=block.end().wrapping_sub(erebor.address_offset);
+            // Line 0 is code the compiler made up
+            let lines: BTreeSet<(&PathBuf, u32)> =
+                trampoline_replay::block_rows(&erebor.lines, &dwarf_block)
+                    .filter(|location| location.line_num != 0)
+                    .map(|location| (&location.file, location.line_num))
+                    .collect();
+            for (file, line) in lines {
+                *files
+                    .entry(file.clone())
+                    .or_default()
+                    .entry(line)
+                    .or_insert(0) += count;
+            }
+        }
+    }
+    Coverage {
+        files: files
+            .into_iter()
+            .map(|(file, lines)| FileCoverage { file, lines })
+            .collect(),
+    }
+}
+
+// The lcov tracefile format, read by genhtml and most editors
+pub fn write_lcov(coverage: &Coverage, out: &mut impl Write) -> std::io::Result<()> {
+    for file in &coverage.files {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", file.file.to_string_lossy())?;
+        for (line, count) in &file.lines {
+            writeln!(out, "DA:{},{}", line, count)?;
+        }
+        writeln!(out, "LF:{}", file.lines.len())?;
+        writeln!(
+            out,
+            "LH:{}",
+            file.lines.values().filter(|count| **count > 0).count()
+        )?;
+        writeln!(out, "end_of_record")?;
+    }
+    Ok(())
+}
+
+// Measures the coverage of a trace exactly like the UI does but
+// writes it to an lcov file instead of serving it.
+pub fn export_lcov(
+    trace: &PathBuf,
+    output: &PathBuf,
+    offset_addrs_with_map: bool,
+    substitute_paths: &[SubstitutePath],
+) -> anyhow::Result<()> {
+    let simulation = Simulation::new(trace.clone(), offset_addrs_with_map, &[], substitute_paths)?;
+    let coverage = measure(&simulation, &JobProgress::default())?;
+    let mut out = BufWriter::new(File::create(output)?);
+    write_lcov(&coverage, &mut out)?;
+    out.flush()?;
+    log::info!(
+        "Wrote the coverage of {} files to {}",
+        coverage.files.len(),
+        output.to_string_lossy()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_structs::{FileInfo, LineLocation};
+
+    #[test]
+    fn lines() {
+        let mut erebor = Erebor {
+            address_offset: 0x10000,
+            ..Erebor::default()
+        };
+        erebor.files.insert(
+            "main.c".into(),
+            FileInfo {
+                functions: vec![Function {
+                    source_file: "main.c".into(),
+                    demangled_name: "main".into(),
+                    address: 0x11000,
+                    size: 0x40,
+                    start_line: 3,
+                    end_line: 9,
+                    return_addresses: Vec::new(),
+                }],
+                ..FileInfo::default()
+            },
+        );
+        let location = |file: &str, line_num| LineLocation {
+            file: file.into(),
+            line_num,
+            column_num: 0,
+        };
+        erebor.lines.insert(0x1000, location("main.c", 3));
+        erebor.lines.insert(0x1006, location("main.c", 5));
+        erebor.lines.insert(0x1010, location("util.h", 20));
+        erebor.lines.insert(0x1020, location("main.c", 0));
+        erebor.lines.insert(0x1030, location("main.c", 8));
+        let executed = Executed {
+            // The last one is in a shared library. Line 5 has no
+            // instrumented instruction of its own.
+            instrumented: vec![0x11004, 0x11008, 0x11014, 0x11024, 0x11034, 0x7f0000],
+            addresses: vec![0x11004, 0x11014, 0x11004, 0x11008, 0x7f0000],
+        };
+        let coverage = line_coverage(&erebor, &executed);
+        assert_eq!(
+            coverage.files,
+            vec![
+                FileCoverage {
+                    file: "main.c".into(),
+                    lines: BTreeMap::from([(3, 3), (5, 2), (8, 0)]),
+                },
+                FileCoverage {
+                    file: "util.h".into(),
+                    lines: BTreeMap::from([(20, 1)]),
+                },
+            ]
+        );
+
+        let mut lcov = Vec::new();
+        write_lcov(&coverage, &mut lcov).unwrap();
+        assert_eq!(
+            String::from_utf8(lcov).unwrap(),
+            "TN:\nSF:main.c\nDA:3,3\nDA:5,2\nDA:8,0\nLF:3\nLH:2\nend_of_record\n\
+             TN:\nSF:util.h\nDA:20,1\nLF:1\nLH:1\nend_of_record\n"
+        );
+    }
+}
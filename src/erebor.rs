@@ -1,7 +1,9 @@
+use crate::config::{self, SubstitutePath};
 use crate::shared_structs::{FileInfo, Function, GraphModule, GraphNode, LineLocation};
 use object::{Object, ObjectSection, ObjectSymbol, ObjectSymbolTable, SectionKind, Segment};
 
 use fallible_iterator::FallibleIterator;
+use iced_x86::{Decoder, DecoderOptions, FlowControl};
 
 use gimli::{Section, UnitHeader, UnitOffset, UnitSectionOffset, UnitType, UnwindSection};
 use regex::bytes::Regex;
@@ -15,7 +17,7 @@ use std::io;
 use std::io::{BufWriter, Write};
 use std::iter::Iterator;
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::result;
 use std::sync::{Condvar, Mutex};
@@ -28,10 +30,30 @@ use typed_arena::Arena;
 //
 // Erebor utilizes gimli
 //
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Erebor {
     pub files: HashMap<PathBuf, FileInfo>,
     pub lines: BTreeMap<usize, LineLocation>,
+    // Functions and the variables visible in them
+    pub scopes: Vec<VariableScope>,
+    pub globals: Vec<(gimli::Encoding, Variable)>,
+    // DIE offset -> type
+    pub types: HashMap<usize, VariableType>,
+    // Added to DWARF addresses to get runtime addresses.
+    // scopes, globals and types are not offset.
+    pub address_offset: usize,
+    // --substitute-path rules, already applied to the file paths
+    pub substitute_paths: Vec<SubstitutePath>,
+    // Unwind rules, to find the CFA of a frame. Not offset either.
+    pub call_frames: CallFrames,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallFrames {
+    pub eh_frame: Vec<u8>,
+    // Where .eh_frame is in the binary, for its pc-relative pointers
+    pub eh_frame_address: u64,
+    pub debug_frame: Vec<u8>,
 }
 
 impl Erebor {
@@ -39,22 +61,22 @@ impl Erebor {
         // let mut functions = HashMap::<PathBuf, Function>::new();
         // let symbols = get_symbols(&obj_file).unwrap();
         // let mut symbols = Vec::new();
-        let mut me = Self {
-            files: HashMap::new(),
-            lines: BTreeMap::new(),
-        };
-        read_file(&obj_file, &mut me);
+        let mut me = Self::unrelocated(&obj_file);
         // TODO
         // This finds the /FIRST/ map and offsets. Bad.
         if offset_addrs_with_map {
             log::info!("Erebor offset map is {:?}", &map);
             // for map in procmap.iter() {
             log::error!("{:?}", map);
+            me.address_offset = map.base;
             // if map.perms.readable && map.perms.executable && !map.perms.writable {
 
             for mut file in me.files.values_mut() {
                 for mut func in file.functions.iter_mut() {
                     func.address += map.base;
+                    for ret_addr in func.return_addresses.iter_mut() {
+                        *ret_addr += map.base;
+                    }
                 }
                 for mut line_entry in file.lines.values_mut() {
                     for mut line_entry_addr in line_entry.iter_mut() {
@@ -82,6 +104,81 @@ impl Erebor {
 
         me
     }
+    // The DWARF data with addresses as they are in the binary
+    pub fn unrelocated(obj_file: &object::File) -> Self {
+        let mut me = Self::default();
+        read_file(obj_file, &mut me);
+        find_return_addresses(obj_file, &mut me);
+        read_call_frames(obj_file, &mut me);
+        me
+    }
+    // Moves every source file path that remap returns a new path for
+    pub fn remap_files(&mut self, remap: impl Fn(&Path) -> Option<PathBuf>) {
+        let remapped = |path: &mut PathBuf| {
+            if let Some(new_path) = remap(path) {
+                *path = new_path;
+            }
+        };
+        self.files = mem::take(&mut self.files)
+            .into_iter()
+            .map(|(mut path, mut file_info)| {
+                remapped(&mut path);
+                for func in file_info.functions.iter_mut() {
+                    remapped(&mut func.source_file);
+                }
+                (path, file_info)
+            })
+            .collect();
+        for location in self.lines.values_mut() {
+            remapped(&mut location.file);
+        }
+    }
+    // Applies the --substitute-path rules to every source file path
+    pub fn substitute_paths(&mut self, rules: Vec<SubstitutePath>) {
+        self.remap_files(|path| config::substitute_path(&rules, path));
+        self.substitute_paths = rules;
+    }
+    // The name of a source file in files. Paths from before the
+    // substitutions, such as in an older session, are substituted.
+    pub fn source_file(&self, path: &Path) -> Option<PathBuf> {
+        if self.files.contains_key(path) {
+            return Some(path.to_path_buf());
+        }
+        let substituted = config::substitute_path(&self.substitute_paths, path)?;
+        self.files.contains_key(&substituted).then_some(substituted)
+    }
+    // The variable named name that is visible at pc, with the encoding
+    // of its unit and the frame base of its function
+    pub fn find_variable(
+        &self,
+        pc: usize,
+        name: &str,
+    ) -> Option<(gimli::Encoding, Option<&Vec<u8>>, &Variable)> {
+        let pc = pc.wrapping_sub(self.address_offset);
+        let scope = self
+            .scopes
+            .iter()
+            .filter(|scope| scope.low_pc <= pc && pc < scope.high_pc)
+            .min_by_key(|scope| scope.high_pc - scope.low_pc);
+        if let Some(scope) = scope {
+            // The innermost block wins when a name is shadowed
+            let variable = scope
+                .variables
+                .iter()
+                .filter(|(block, variable)| {
+                    variable.name == name
+                        && block.map_or(true, |(low_pc, high_pc)| low_pc <= pc && pc < high_pc)
+                })
+                .min_by_key(|(block, _)| block.map_or(usize::MAX, |(low_pc, high_pc)| high_pc - low_pc));
+            if let Some((_, variable)) = variable {
+                return Some((scope.encoding, scope.frame_base.as_ref(), variable));
+            }
+        }
+        self.globals
+            .iter()
+            .find(|(_, variable)| variable.name == name)
+            .map(|(encoding, variable)| (*encoding, None, variable))
+    }
     pub fn get_func_for_addr(&self, file: &PathBuf, addr: usize)->Option<&Function>{
         let file = self.files.get(file);
 
@@ -95,12 +192,64 @@ impl Erebor {
         }
         None
     }
+    // The function whose code addr is in, in any file
+    pub fn function_at(&self, addr: usize) -> Option<&Function> {
+        self.files
+            .values()
+            .flat_map(|file| &file.functions)
+            .find(|func| func.address <= addr && addr < func.address + func.size)
+    }
 }
 
 struct SourceFile {
     path: PathBuf,
 }
 
+// Decode the code of every function to find where it returns.
+// Tail calls (a jmp into another function) are not counted.
+fn find_return_addresses(obj_file: &object::File, erebor: &mut Erebor) {
+    let code: Vec<(u64, &[u8])> = obj_file
+        .sections()
+        .filter(|section| section.kind() == SectionKind::Text)
+        .filter_map(|section| Some((section.address(), section.data().ok()?)))
+        .collect();
+    for file in erebor.files.values_mut() {
+        for func in file.functions.iter_mut() {
+            let start = func.address as u64;
+            let end = start + func.size as u64;
+            let Some((section_addr, data)) = code
+                .iter()
+                .find(|(addr, data)| *addr <= start && end <= *addr + data.len() as u64)
+            else {
+                log::warn!("No code found for function {}", func.demangled_name);
+                continue;
+            };
+            let bytes = &data[(start - section_addr) as usize..(end - section_addr) as usize];
+            let decoder = Decoder::with_ip(64, bytes, start, DecoderOptions::NONE);
+            func.return_addresses = decoder
+                .into_iter()
+                .filter(|instr| instr.flow_control() == FlowControl::Return)
+                .map(|instr| instr.ip() as usize)
+                .collect();
+        }
+    }
+}
+
+fn read_call_frames(obj_file: &object::File, erebor: &mut Erebor) {
+    let section = |name: &str| {
+        let section = obj_file.section_by_name(name)?;
+        let data = section.uncompressed_data().ok()?;
+        Some((section.address(), data.into_owned()))
+    };
+    if let Some((address, data)) = section(".eh_frame") {
+        erebor.call_frames.eh_frame = data;
+        erebor.call_frames.eh_frame_address = address;
+    }
+    if let Some((_, data)) = section(".debug_frame") {
+        erebor.call_frames.debug_frame = data;
+    }
+}
+
 struct LineEntry {
     line: u32,
     column: u32,
@@ -471,6 +620,7 @@ where
     if flags.info {
         dump_info(w, &dwarf, dwo_parent_units, flags, erebor)?;
         dump_types(w, &dwarf, dwo_parent_units, flags, erebor)?;
+        dump_variables(w, &dwarf, erebor)?;
     }
     if flags.line {
         dump_line(w, &dwarf, erebor)?;
@@ -944,6 +1094,7 @@ fn dump_entries<R: Reader, W: Write>(
                 size: size.unwrap(),
                 start_line: start_line.unwrap(),
                 end_line: end_line.unwrap(),
+                return_addresses: Vec::new(),
             };
             let mut file_info: FileInfo = erebor
                 .files
@@ -1885,3 +2036,411 @@ fn dump_aranges<R: Reader, W: Write>(
     }
     Ok(())
 }
+
+// Variables and types used to read captured values out of a replay.
+//
+// Only what is needed to find a variable by name at a pc and
+// to format its value is kept. Addresses are the raw DWARF ones,
+// see Erebor::address_offset.
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    // Offset of the type DIE in .debug_info
+    pub type_offset: Option<usize>,
+    // (begin pc, end pc, DWARF expression). Empty if optimized out
+    pub locations: Vec<(usize, usize, Vec<u8>)>,
+}
+impl Variable {
+    pub fn location_at(&self, pc: usize) -> Option<&Vec<u8>> {
+        self.locations
+            .iter()
+            .find(|(begin, end, _)| *begin <= pc && pc < *end)
+            .map(|(_, _, expr)| expr)
+    }
+}
+#[derive(Debug, Clone)]
+pub struct VariableScope {
+    pub low_pc: usize,
+    pub high_pc: usize,
+    pub encoding: gimli::Encoding,
+    pub frame_base: Option<Vec<u8>>,
+    // Variables declared in a lexical block carry the pc range of the block
+    pub variables: Vec<(Option<(usize, usize)>, Variable)>,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseEncoding {
+    Signed,
+    Unsigned,
+    Float,
+    Bool,
+    SignedChar,
+    UnsignedChar,
+    Other,
+}
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    // None when the location is an expression other than DW_OP_plus_uconst
+    pub offset: Option<usize>,
+    pub type_offset: Option<usize>,
+}
+#[derive(Debug, Clone)]
+pub enum VariableType {
+    Base {
+        name: String,
+        size: usize,
+        encoding: BaseEncoding,
+    },
+    Pointer {
+        target: Option<usize>,
+    },
+    Struct {
+        name: String,
+        size: usize,
+        members: Vec<Member>,
+    },
+    Array {
+        element: Option<usize>,
+        count: Option<usize>,
+    },
+    Enum {
+        size: usize,
+        values: Vec<(i64, String)>,
+    },
+    // typedef, const, volatile...
+    Alias {
+        target: Option<usize>,
+    },
+}
+
+fn dump_variables<R: Reader, W: Write>(
+    w: &mut W,
+    dwarf: &gimli::Dwarf<R>,
+    erebor: &mut Erebor,
+) -> Result<()> {
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        let unit = match dwarf.unit(header) {
+            Ok(unit) => unit,
+            Err(err) => {
+                writeln_error(
+                    w,
+                    dwarf,
+                    err.into(),
+                    "Failed to parse unit root entry for dump_variables",
+                )?;
+                continue;
+            }
+        };
+        if let Err(err) = dump_unit_variables(&unit, dwarf, erebor) {
+            writeln_error(w, dwarf, err, "Failed to dump variables")?;
+        }
+    }
+    Ok(())
+}
+
+fn dump_unit_variables<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    erebor: &mut Erebor,
+) -> Result<()> {
+    let mut entries = unit.entries();
+    let mut depth: isize = 0;
+    // (depth of the DIE, index in erebor.scopes)
+    let mut scopes: Vec<(isize, usize)> = Vec::new();
+    // (depth of the DIE, pc range)
+    let mut blocks: Vec<(isize, (usize, usize))> = Vec::new();
+    // (depth of the DIE, offset in erebor.types) for DIEs with children we read
+    let mut parent_type: Option<(isize, usize)> = None;
+    while let Some((delta, entry)) = entries.next_dfs()? {
+        depth += delta;
+        while scopes.last().map_or(false, |(d, _)| depth <= *d) {
+            scopes.pop();
+        }
+        while blocks.last().map_or(false, |(d, _)| depth <= *d) {
+            blocks.pop();
+        }
+        if parent_type.map_or(false, |(d, _)| depth <= d) {
+            parent_type = None;
+        }
+        let offset = die_offset(unit, entry.offset());
+        let type_offset = attr_type_offset(unit, entry)?;
+        match entry.tag() {
+            gimli::DW_TAG_subprogram => {
+                if let Some((low_pc, high_pc)) = die_pc_range(unit, dwarf, entry)? {
+                    let frame_base = match entry.attr_value(gimli::DW_AT_frame_base)? {
+                        Some(gimli::AttributeValue::Exprloc(expr)) => {
+                            Some(expr.0.to_slice()?.into_owned())
+                        }
+                        _ => None,
+                    };
+                    erebor.scopes.push(VariableScope {
+                        low_pc,
+                        high_pc,
+                        encoding: unit.encoding(),
+                        frame_base,
+                        variables: Vec::new(),
+                    });
+                    scopes.push((depth, erebor.scopes.len() - 1));
+                }
+            }
+            gimli::DW_TAG_lexical_block => {
+                if let Some(range) = die_pc_range(unit, dwarf, entry)? {
+                    blocks.push((depth, range));
+                }
+            }
+            gimli::DW_TAG_variable | gimli::DW_TAG_formal_parameter => {
+                let Some(name) = attr_name(unit, dwarf, entry)? else {
+                    continue;
+                };
+                let locations = match entry.attr_value(gimli::DW_AT_location)? {
+                    Some(value) => die_locations(unit, dwarf, value)?,
+                    None => Vec::new(),
+                };
+                let variable = Variable {
+                    name,
+                    type_offset,
+                    locations,
+                };
+                match scopes.last() {
+                    Some((_, index)) => erebor.scopes[*index]
+                        .variables
+                        .push((blocks.last().map(|(_, range)| *range), variable)),
+                    // Skip extern declarations of globals
+                    None if !variable.locations.is_empty() => {
+                        erebor.globals.push((unit.encoding(), variable))
+                    }
+                    None => {}
+                }
+            }
+            gimli::DW_TAG_base_type => {
+                let encoding = match entry.attr_value(gimli::DW_AT_encoding)? {
+                    Some(gimli::AttributeValue::Encoding(encoding)) => match encoding {
+                        gimli::DW_ATE_signed => BaseEncoding::Signed,
+                        gimli::DW_ATE_unsigned => BaseEncoding::Unsigned,
+                        gimli::DW_ATE_float => BaseEncoding::Float,
+                        gimli::DW_ATE_boolean => BaseEncoding::Bool,
+                        gimli::DW_ATE_signed_char => BaseEncoding::SignedChar,
+                        gimli::DW_ATE_unsigned_char => BaseEncoding::UnsignedChar,
+                        _ => BaseEncoding::Other,
+                    },
+                    _ => BaseEncoding::Other,
+                };
+                erebor.types.insert(
+                    offset,
+                    VariableType::Base {
+                        name: attr_name(unit, dwarf, entry)?.unwrap_or_default(),
+                        size: attr_byte_size(entry)?.unwrap_or(0),
+                        encoding,
+                    },
+                );
+            }
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type => {
+                erebor.types.insert(
+                    offset,
+                    VariableType::Pointer {
+                        target: type_offset,
+                    },
+                );
+            }
+            gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_class_type => {
+                erebor.types.insert(
+                    offset,
+                    VariableType::Struct {
+                        name: attr_name(unit, dwarf, entry)?.unwrap_or_default(),
+                        size: attr_byte_size(entry)?.unwrap_or(0),
+                        members: Vec::new(),
+                    },
+                );
+                parent_type = Some((depth, offset));
+            }
+            gimli::DW_TAG_member => {
+                let Some((d, parent)) = parent_type else {
+                    continue;
+                };
+                if depth != d + 1 {
+                    continue;
+                }
+                let member_offset = match entry.attr_value(gimli::DW_AT_data_member_location)? {
+                    Some(gimli::AttributeValue::Exprloc(expr)) => plus_uconst(expr),
+                    Some(value) => value.udata_value().map(|offset| offset as usize),
+                    // union members
+                    None => Some(0),
+                };
+                if let Some(VariableType::Struct { members, .. }) = erebor.types.get_mut(&parent) {
+                    members.push(Member {
+                        name: attr_name(unit, dwarf, entry)?.unwrap_or_default(),
+                        offset: member_offset,
+                        type_offset,
+                    });
+                }
+            }
+            gimli::DW_TAG_array_type => {
+                erebor.types.insert(
+                    offset,
+                    VariableType::Array {
+                        element: type_offset,
+                        count: None,
+                    },
+                );
+                parent_type = Some((depth, offset));
+            }
+            gimli::DW_TAG_subrange_type => {
+                let Some((_, parent)) = parent_type else {
+                    continue;
+                };
+                let count = match entry.attr_value(gimli::DW_AT_count)? {
+                    Some(value) => value.udata_value(),
+                    None => entry
+                        .attr_value(gimli::DW_AT_upper_bound)?
+                        .and_then(|value| value.udata_value())
+                        .map(|upper_bound| upper_bound + 1),
+                };
+                if let Some(VariableType::Array { count: c, .. }) = erebor.types.get_mut(&parent) {
+                    // Only the outermost dimension
+                    if c.is_none() {
+                        *c = count.map(|count| count as usize);
+                    }
+                }
+            }
+            gimli::DW_TAG_enumeration_type => {
+                erebor.types.insert(
+                    offset,
+                    VariableType::Enum {
+                        size: attr_byte_size(entry)?.unwrap_or(4),
+                        values: Vec::new(),
+                    },
+                );
+                parent_type = Some((depth, offset));
+            }
+            gimli::DW_TAG_enumerator => {
+                let Some((_, parent)) = parent_type else {
+                    continue;
+                };
+                let value = match entry.attr_value(gimli::DW_AT_const_value)? {
+                    Some(gimli::AttributeValue::Sdata(value)) => value,
+                    Some(value) => value.udata_value().unwrap_or(0) as i64,
+                    None => continue,
+                };
+                let name = attr_name(unit, dwarf, entry)?.unwrap_or_default();
+                if let Some(VariableType::Enum { values, .. }) = erebor.types.get_mut(&parent) {
+                    values.push((value, name));
+                }
+            }
+            gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_restrict_type
+            | gimli::DW_TAG_atomic_type => {
+                erebor.types.insert(
+                    offset,
+                    VariableType::Alias {
+                        target: type_offset,
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn die_offset<R: Reader>(unit: &gimli::Unit<R>, offset: gimli::UnitOffset<usize>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(o) => o.0,
+        UnitSectionOffset::DebugTypesOffset(o) => o.0,
+    }
+}
+
+fn attr_type_offset<R: Reader>(
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<usize>> {
+    Ok(match entry.attr_value(gimli::DW_AT_type)? {
+        Some(gimli::AttributeValue::UnitRef(offset)) => Some(die_offset(unit, offset)),
+        Some(gimli::AttributeValue::DebugInfoRef(offset)) => Some(offset.0),
+        _ => None,
+    })
+}
+
+fn attr_name<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<String>> {
+    match entry.attr_value(gimli::DW_AT_name)? {
+        Some(value) => Ok(Some(
+            dwarf
+                .attr_string(unit, value)?
+                .to_string_lossy()?
+                .into_owned(),
+        )),
+        None => Ok(None),
+    }
+}
+
+fn attr_byte_size<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>) -> Result<Option<usize>> {
+    Ok(entry
+        .attr_value(gimli::DW_AT_byte_size)?
+        .and_then(|value| value.udata_value())
+        .map(|size| size as usize))
+}
+
+// The offset of a DW_OP_plus_uconst member location, the form
+// compilers use when the offset is not a constant
+fn plus_uconst<R: Reader>(expr: gimli::Expression<R>) -> Option<usize> {
+    let mut bytes = expr.0;
+    if bytes.read_u8().ok()? != gimli::DW_OP_plus_uconst.0 {
+        return None;
+    }
+    let offset = bytes.read_uleb128().ok()?;
+    bytes.is_empty().then_some(offset as usize)
+}
+
+fn die_pc_range<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<(usize, usize)>> {
+    let low_pc = match entry.attr_value(gimli::DW_AT_low_pc)? {
+        Some(value) => dwarf.attr_address(unit, value)?,
+        None => None,
+    };
+    let Some(low_pc) = low_pc else {
+        return Ok(None);
+    };
+    let high_pc = match entry.attr_value(gimli::DW_AT_high_pc)? {
+        Some(gimli::AttributeValue::Addr(high_pc)) => high_pc,
+        Some(value) => match value.udata_value() {
+            Some(size) => low_pc + size,
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    Ok(Some((low_pc as usize, high_pc as usize)))
+}
+
+fn die_locations<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    value: gimli::AttributeValue<R>,
+) -> Result<Vec<(usize, usize, Vec<u8>)>> {
+    if let gimli::AttributeValue::Exprloc(expr) = value {
+        return Ok(vec![(0, usize::MAX, expr.0.to_slice()?.into_owned())]);
+    }
+    let mut locations = Vec::new();
+    if let Some(mut iter) = dwarf.attr_locations(unit, value)? {
+        while let Some(entry) = iter.next()? {
+            locations.push((
+                entry.range.begin as usize,
+                entry.range.end as usize,
+                entry.data.0.to_slice()?.into_owned(),
+            ));
+        }
+    }
+    Ok(locations)
+}
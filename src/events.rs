@@ -0,0 +1,71 @@
+use std::convert::Infallible;
+
+use actix_web::web::Bytes;
+use futures_util::future;
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::shared_structs::ServerEvent;
+
+// Events kept for clients that fall behind. They skip the older ones,
+// which is fine as every event carries the latest version.
+const EVENT_CAPACITY: usize = 256;
+
+// Fans ServerEvents out to every client of /events
+pub struct EventBus {
+    sender: broadcast::Sender<ServerEvent>,
+}
+impl Default for EventBus {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { sender }
+    }
+}
+impl EventBus {
+    pub fn send(&self, event: ServerEvent) {
+        // Nobody listening is not an error
+        let _ = self.sender.send(event);
+    }
+    // A server-sent event stream that starts with the current state
+    // so a client that connects late is in sync
+    pub fn stream(&self, current: Vec<ServerEvent>) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        let receiver = self.sender.subscribe();
+        let later = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        stream::iter(current)
+            .chain(later)
+            .filter_map(|event| future::ready(encode(&event).map(Ok)))
+    }
+}
+fn encode(event: &ServerEvent) -> Option<Bytes> {
+    let json = serde_json::to_string(event).ok()?;
+    Some(Bytes::from(format!("data: {}\n\n", json)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        assert_eq!(
+            encode(&ServerEvent::Settings { version: 3 }).unwrap(),
+            Bytes::from("data: {\"type\":\"settings\",\"version\":3}\n\n")
+        );
+        assert_eq!(
+            encode(&ServerEvent::Graph {
+                trace_id: 1,
+                version: 2
+            })
+            .unwrap(),
+            Bytes::from("data: {\"type\":\"graph\",\"trace_id\":1,\"version\":2}\n\n")
+        );
+    }
+}
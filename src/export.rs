@@ -0,0 +1,97 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use clap::ValueEnum;
+
+use crate::config::SubstitutePath;
+use crate::file_parsing::FunctionPattern;
+use crate::shared_structs::Settings;
+use crate::simulation::Simulation;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Graphviz source
+    Dot,
+    /// Rendered with the graphviz `dot` binary
+    Svg,
+    /// Nodes, edges and modules of the inferred graph
+    Json,
+}
+
+// Builds the graph for a trace exactly like `explore` does
+// but writes it to a file instead of serving it.
+pub fn export(
+    trace: &PathBuf,
+    format: ExportFormat,
+    output: &PathBuf,
+    offset_addrs_with_map: bool,
+    partition_by_thread: bool,
+    function_patterns: &[FunctionPattern],
+    substitute_paths: &[SubstitutePath],
+) -> anyhow::Result<()> {
+    let simulation = Simulation::new(
+        trace.clone(),
+        offset_addrs_with_map,
+        function_patterns,
+        substitute_paths,
+    )?;
+    let dwarf_data = simulation
+        .dwarf_data
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let mut graph_builder = simulation
+        .graph_builder
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    let settings = Settings {
+        partition_by_thread,
+        ..Settings::default()
+    };
+    graph_builder.set_partition_by_thread(partition_by_thread)?;
+
+    let data = match format {
+        ExportFormat::Dot | ExportFormat::Svg => {
+            let dot = graph_builder
+                .get_graph_as_dot(&dwarf_data, &settings)?
+                .ok_or_else(|| anyhow::anyhow!("The graph was not prepared"))?;
+            if format == ExportFormat::Svg {
+                render_svg(&dot)?
+            } else {
+                dot.into_bytes()
+            }
+        }
+        ExportFormat::Json => {
+            let export = graph_builder
+                .get_graph_export()?
+                .ok_or_else(|| anyhow::anyhow!("The graph was not prepared"))?;
+            serde_json::to_vec_pretty(&export)?
+        }
+    };
+    std::fs::write(output, data)?;
+    log::info!("Wrote graph to {}", output.to_string_lossy());
+    Ok(())
+}
+
+fn render_svg(dot: &str) -> anyhow::Result<Vec<u8>> {
+    let mut child = Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Unable to run graphviz `dot` (is it installed?): {}", e))?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Unable to open stdin of `dot`"))?
+        .write_all(dot.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`dot` failed to render the graph: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(output.stdout)
+}
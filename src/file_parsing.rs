@@ -1,34 +1,64 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    annotation_writer::SourceSnapshot,
     erebor::Erebor,
     graph_builder::GraphBuilder,
-    shared_structs::{GraphModule, GraphNode, LineLocation},
+    shared_structs::{AnnotationOrigin, GraphModule, GraphNode, LaunchInfo, LineLocation},
 };
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io;
 
-pub fn parse_annotations(erebor: &Erebor, graph_builder: &mut GraphBuilder) -> anyhow::Result<()> {
-    let mut modules: HashMap<String, GraphModule> = HashMap::new();
-    let mut nodes: HashMap<usize, GraphNode> = HashMap::new();
+// [[{...}]] with the inside of the braces captured
+pub const ANNOTATION_PATTERN: &str = r"\[\[\{(.*)\}\]\]";
+pub const SIDECAR_FILE_NAME: &str = "explorant.annotations.json5";
+
+// A span annotation becomes a node named after the span plus one of these
+pub const SPAN_BEGIN_SUFFIX: &str = " begin";
+pub const SPAN_END_SUFFIX: &str = " end";
+// Names of the nodes made for a function by a functions annotation
+pub const FUNCTION_ENTRY_SUFFIX: &str = " entry";
+pub const FUNCTION_EXIT_SUFFIX: &str = " exit";
+
+// Functions given on the command line rather than in an annotation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionPattern {
+    pub pattern: Option<String>,
+    pub file: Option<String>,
+    pub module: String,
+}
+
+pub fn parse_annotations(
+    erebor: &Erebor,
+    graph_builder: &mut GraphBuilder,
+    function_patterns: &[FunctionPattern],
+    sidecar: Option<&Path>,
+) -> anyhow::Result<()> {
+    let mut annotations = ParsedAnnotations {
+        modules: HashMap::new(),
+        nodes: HashMap::new(),
+        fake_addr: 0,
+        function_patterns: function_patterns.to_vec(),
+    };
     // we are not guaranteed to read the files
     // in any particular order so the FQNs of the nodes
     // will actually be their module::name and the
     // FQN will be resolved later.
-    modules.insert(
+    annotations.modules.insert(
         "".into(),
         GraphModule {
             name: "".into(),
             parent: None,
             module_attributes: HashMap::new(),
+            origin: AnnotationOrigin::New,
         },
     );
+    let mut sources = HashMap::new();
 
-    let mut fake_addr = 0;
     for (file_name, file_info) in &erebor.files {
         log::info!("reading file {} ", file_name.to_string_lossy());
         let file = File::open(file_name);
@@ -36,96 +66,397 @@ pub fn parse_annotations(erebor: &Erebor, graph_builder: &mut GraphBuilder) -> a
             log::warn!("Skipping reading file {} due to an error", file_name.to_string_lossy());
             continue;
         };
-        let reader = BufReader::new(file);
+        let text = io::read_to_string(file)?;
+        let mut snapshot = SourceSnapshot::new(&text);
 
-        'line: for (line_num, line) in reader.lines().enumerate() {
-            let anno = parse_line(&line?)?;
+        'line: for (line_num, line) in text.lines().enumerate() {
+            let anno = parse_line(line)?;
             let Some(anno) = anno else {
                 continue 'line;
             };
-            match anno {
-                Annotation::Module {
-                    name,
-                    parent_module,
-                    comment,
-                } => {
-                    if modules
-                        .insert(
-                            name.clone(),
-                            GraphModule {
-                                name: name.clone(),
-                                // point to the root node rather than nothing
-                                parent: Some(parent_module.unwrap_or("".into())),
-                                module_attributes: HashMap::new(),
-                            },
-                        )
-                        .is_some()
-                    {
-                        return Err(anyhow::anyhow!("Duplicate module entry ({})", name));
-                    }
+            // functions annotations are not edited in the UI
+            if !matches!(anno, Annotation::Functions { .. }) {
+                snapshot.record_annotation(1 + line_num as u32, None);
+            }
+            let origin = AnnotationOrigin::Source {
+                file: file_name.clone(),
+                line: 1 + line_num as u32,
+            };
+            annotations.add(anno, file_name, line_num, origin)?;
+        }
+        sources.insert(file_name.clone(), snapshot);
+    }
+    if let Some(sidecar) = sidecar {
+        parse_sidecar(erebor, &mut annotations, sidecar)?;
+    }
+    let ParsedAnnotations {
+        mut modules,
+        mut nodes,
+        mut fake_addr,
+        function_patterns,
+    } = annotations;
+    for function_pattern in &function_patterns {
+        // Modules only used for functions do not need their own annotation
+        if !modules.contains_key(&function_pattern.module) {
+            modules.insert(
+                function_pattern.module.clone(),
+                GraphModule {
+                    name: function_pattern.module.clone(),
+                    parent: Some("".into()),
+                    module_attributes: HashMap::new(),
+                    origin: AnnotationOrigin::Functions,
+                },
+            );
+        }
+        for node in function_nodes(erebor, function_pattern)? {
+            fake_addr += 1;
+            nodes.insert(fake_addr, node);
+        }
+    }
+    // for mut node in &mut nodes.values_mut() {
+    //     node.FQN = name_to_fqn(&node.FQN, &modules)?;
+    // }
+    graph_builder.update_raw_modules(modules)?;
+    graph_builder.update_raw_nodes(nodes, erebor)?;
+    // Remember where each node ended up to tell later if it was moved
+    for node in graph_builder.nodes.values() {
+        if let AnnotationOrigin::Source { file, line } = &node.origin {
+            if let Some(snapshot) = sources.get_mut(file) {
+                snapshot.record_annotation(*line, Some(node.location.line_num));
+            }
+        }
+    }
+    graph_builder.sources = sources;
+    Ok(())
+}
+// Everything declared by the annotations read so far.
+// nodes is keyed by a fake address until graph_builder finds the real one.
+struct ParsedAnnotations {
+    modules: HashMap<String, GraphModule>,
+    nodes: HashMap<usize, GraphNode>,
+    fake_addr: usize,
+    // Expanded once every module is known
+    function_patterns: Vec<FunctionPattern>,
+}
+impl ParsedAnnotations {
+    // Adds what anno declares. Returns the key in nodes of the
+    // node it created, if any.
+    fn add(
+        &mut self,
+        anno: Annotation,
+        file_name: &PathBuf,
+        line_num: usize,
+        origin: AnnotationOrigin,
+    ) -> anyhow::Result<Option<usize>> {
+        let last_fake_addr = self.fake_addr;
+        match anno {
+            Annotation::Module {
+                name,
+                parent_module,
+                comment,
+            } => {
+                if self.modules
+                    .insert(
+                        name.clone(),
+                        GraphModule {
+                            name: name.clone(),
+                            // point to the root node rather than nothing
+                            parent: Some(parent_module.unwrap_or("".into())),
+                            module_attributes: HashMap::new(),
+                            origin,
+                        },
+                    )
+                    .is_some()
+                {
+                    return Err(anyhow::anyhow!("Duplicate module entry ({})", name));
                 }
-                Annotation::Event { name } => {
-                    fake_addr += 1;
-                    log::info!("Registered event {}, ", name);
+            }
+            Annotation::Event { name, capture, when } => {
+                self.fake_addr += 1;
+                log::info!("Registered event {}, ", name);
 
-                    let mut t_name = name.clone();
-                    let mut mn_iter = t_name.split("::");
-                    let m_name = mn_iter.next().ok_or(anyhow::anyhow!("Event {name} does not have a module"))?;
-                    let n_name = mn_iter.next().ok_or(anyhow::anyhow!("Event {name} does not have a node"))?;
-                    nodes.insert(
-                        fake_addr, // WILL BE REPLACED BY graph_builder
-                        GraphNode {
-                            FQN: "".into(), // WILL BE REPLACED BY graph_builder
-                            address: 0, // WILL BE REPLACED BY graph_builder
-                            module: m_name.into(),
-                            name: n_name.into(),
-                            node_type: "Event".into(),
-                            location: LineLocation {
-                                file: file_name.clone(),
-                                line_num: 1+line_num as u32, // WILL BE REPLACED BY graph_builder
-                                column_num: 0,
-                            },
-                            labeled_transitions: Vec::new(),
-                            node_attributes: HashMap::new(),
+                let mut t_name = name.clone();
+                let mut mn_iter = t_name.split("::");
+                let m_name = mn_iter.next().ok_or(anyhow::anyhow!("Event {name} does not have a module"))?;
+                let n_name = mn_iter.next().ok_or(anyhow::anyhow!("Event {name} does not have a node"))?;
+                self.nodes.insert(
+                    self.fake_addr, // WILL BE REPLACED BY graph_builder
+                    GraphNode {
+                        FQN: "".into(), // WILL BE REPLACED BY graph_builder
+                        address: 0, // WILL BE REPLACED BY graph_builder
+                        module: m_name.into(),
+                        name: n_name.into(),
+                        node_type: "Event".into(),
+                        location: LineLocation {
+                            file: file_name.clone(),
+                            line_num: 1+line_num as u32, // WILL BE REPLACED BY graph_builder
+                            column_num: 0,
                         },
-                    );
-                }
-                Annotation::Flow { name } => {
-                    fake_addr += 1;
-                    log::info!("Registered event {}, ", name);
+                        labeled_transitions: Vec::new(),
+                        node_attributes: HashMap::new(),
+                        captures: capture,
+                        when,
+                        fixed_address: false,
+                        origin,
+                    },
+                );
+            }
+            Annotation::SpanBegin { name } => {
+                self.fake_addr += 1;
+                log::info!("Registered span begin {}, ", name);
+                self.nodes.insert(
+                    self.fake_addr,
+                    span_node(&name, true, file_name, line_num, origin)?,
+                );
+            }
+            Annotation::SpanEnd { name } => {
+                self.fake_addr += 1;
+                log::info!("Registered span end {}, ", name);
+                self.nodes.insert(
+                    self.fake_addr,
+                    span_node(&name, false, file_name, line_num, origin)?,
+                );
+            }
+            Annotation::Functions {
+                pattern,
+                file,
+                module,
+            } => {
+                self.function_patterns.push(FunctionPattern {
+                    pattern,
+                    file,
+                    module,
+                });
+            }
+            Annotation::Flow { name } => {
+                self.fake_addr += 1;
+                log::info!("Registered event {}, ", name);
 
-                    let mut t_name = name.clone();
-                    let mut mn_iter = t_name.split("::");
-                    let m_name = mn_iter.next().ok_or(anyhow::anyhow!("Flow {name} does not have a module"))?;
-                    let n_name = mn_iter.next().ok_or(anyhow::anyhow!("Flow {name} does not have a node"))?;
-                    nodes.insert(
-                        fake_addr, // WILL BE REPLACED BY graph_builder
-                        GraphNode {
-                            FQN: "".into(), // WILL BE REPLACED BY graph_builder
-                            address: 0, // WILL BE REPLACED BY graph_builder
-                            module: m_name.into(),
-                            name: n_name.into(),
-                            node_type: "Flow".into(),
-                            location: LineLocation {
-                                file: file_name.clone(),
-                                line_num: line_num as u32, // WILL BE REPLACED BY graph_builder
-                                column_num: 0,
-                            },
-                            labeled_transitions: Vec::new(),
-                            node_attributes: HashMap::new(),
+                let mut t_name = name.clone();
+                let mut mn_iter = t_name.split("::");
+                let m_name = mn_iter.next().ok_or(anyhow::anyhow!("Flow {name} does not have a module"))?;
+                let n_name = mn_iter.next().ok_or(anyhow::anyhow!("Flow {name} does not have a node"))?;
+                self.nodes.insert(
+                    self.fake_addr, // WILL BE REPLACED BY graph_builder
+                    GraphNode {
+                        FQN: "".into(), // WILL BE REPLACED BY graph_builder
+                        address: 0, // WILL BE REPLACED BY graph_builder
+                        module: m_name.into(),
+                        name: n_name.into(),
+                        node_type: "Flow".into(),
+                        location: LineLocation {
+                            file: file_name.clone(),
+                            line_num: line_num as u32, // WILL BE REPLACED BY graph_builder
+                            column_num: 0,
                         },
-                    );
-                }
+                        labeled_transitions: Vec::new(),
+                        node_attributes: HashMap::new(),
+                        captures: Vec::new(),
+                        when: None,
+                        fixed_address: false,
+                        origin,
+                    },
+                );
+            }
+        }
+        Ok((self.fake_addr != last_fake_addr).then_some(self.fake_addr))
+    }
+}
+
+// The sidecar is a JSON5 list of annotations for code that cannot be
+// edited. Events, flows and spans say where they are with one of
+// line:"file.c:123", function:"name" or address:0x1234.
+fn parse_sidecar(
+    erebor: &Erebor,
+    annotations: &mut ParsedAnnotations,
+    sidecar: &Path,
+) -> anyhow::Result<()> {
+    log::info!("reading sidecar annotations {}", sidecar.to_string_lossy());
+    let text = std::fs::read_to_string(sidecar)?;
+    let entries: Vec<SidecarAnnotation> = json5::from_str(&text)
+        .map_err(|e| anyhow::anyhow!("Invalid {}: {}", sidecar.to_string_lossy(), e))?;
+    for entry in entries {
+        let has_location =
+            entry.line.is_some() || entry.function.is_some() || entry.address.is_some();
+        let key = annotations.add(entry.annotation, &PathBuf::new(), 0, AnnotationOrigin::Sidecar)?;
+        let Some(key) = key else {
+            continue;
+        };
+        let node = annotations.nodes.get_mut(&key).unwrap();
+        if !has_location {
+            anyhow::bail!(
+                "{}::{} in {} needs a line, function or address",
+                node.module,
+                node.name,
+                sidecar.to_string_lossy()
+            );
+        }
+        if let Some(line) = &entry.line {
+            let (file, line_num) = line
+                .rsplit_once(':')
+                .and_then(|(file, line_num)| Some((file, line_num.parse::<u32>().ok()?)))
+                .ok_or_else(|| anyhow::anyhow!("Invalid line {}, expected file:line", line))?;
+            node.location = LineLocation {
+                file: find_source_file(erebor, file)?,
+                line_num,
+                column_num: 0,
+            };
+        } else if let Some(function) = &entry.function {
+            let func = erebor
+                .files
+                .values()
+                .flat_map(|file_info| &file_info.functions)
+                .find(|func| func.demangled_name == *function)
+                .ok_or_else(|| anyhow::anyhow!("No function named {}", function))?;
+            node.address = func.address;
+            node.fixed_address = true;
+            node.location = LineLocation {
+                file: func.source_file.clone(),
+                line_num: func.start_line,
+                column_num: 0,
+            };
+        } else if let Some(address) = entry.address {
+            // Addresses are as shown by objdump, before the binary is moved
+            node.address = address + erebor.address_offset;
+            node.fixed_address = true;
+            if let Some((_, location)) = erebor.lines.range(..=address).next_back() {
+                node.location = location.clone();
             }
         }
     }
-    // for mut node in &mut nodes.values_mut() {
-    //     node.FQN = name_to_fqn(&node.FQN, &modules)?;
-    // }
-    graph_builder.update_raw_modules(modules);
-    graph_builder.update_raw_nodes(nodes, &erebor);
     Ok(())
 }
+#[derive(Deserialize, Debug, PartialEq)]
+struct SidecarAnnotation {
+    #[serde(flatten)]
+    annotation: Annotation,
+    line: Option<String>,
+    function: Option<String>,
+    address: Option<usize>,
+}
+// The one DWARF source file that ends with path
+fn find_source_file(erebor: &Erebor, path: &str) -> anyhow::Result<PathBuf> {
+    let matches: Vec<&PathBuf> = erebor
+        .files
+        .keys()
+        .filter(|file| file.ends_with(path))
+        .collect();
+    match matches.as_slice() {
+        [file] => Ok((*file).clone()),
+        [] => anyhow::bail!("No source file {} in the DWARF data", path),
+        _ => anyhow::bail!("{} matches several source files: {:?}", path, matches),
+    }
+}
+// The sidecar next to the recording wins over the one in the
+// directory the program was recorded from
+pub fn find_sidecar(recording_dir: &Path, launch_info: Option<&LaunchInfo>) -> Option<PathBuf> {
+    let project_dir = launch_info
+        .and_then(|info| info.cwd.clone())
+        .or_else(|| std::env::current_dir().ok());
+    std::iter::once(recording_dir.to_path_buf())
+        .chain(project_dir)
+        .map(|dir| dir.join(SIDECAR_FILE_NAME))
+        .find(|path| path.exists())
+}
+fn span_node(
+    name: &str,
+    is_begin: bool,
+    file_name: &PathBuf,
+    line_num: usize,
+    origin: AnnotationOrigin,
+) -> anyhow::Result<GraphNode> {
+    let mut mn_iter = name.split("::");
+    let m_name = mn_iter.next().ok_or(anyhow::anyhow!("Span {name} does not have a module"))?;
+    let n_name = mn_iter.next().ok_or(anyhow::anyhow!("Span {name} does not have a node"))?;
+    let (suffix, node_type) = match is_begin {
+        true => (SPAN_BEGIN_SUFFIX, "SpanBegin"),
+        false => (SPAN_END_SUFFIX, "SpanEnd"),
+    };
+    Ok(GraphNode {
+        FQN: "".into(), // WILL BE REPLACED BY graph_builder
+        address: 0, // WILL BE REPLACED BY graph_builder
+        module: m_name.into(),
+        name: format!("{}{}", n_name, suffix),
+        node_type: node_type.into(),
+        location: LineLocation {
+            file: file_name.clone(),
+            line_num: 1+line_num as u32, // WILL BE REPLACED BY graph_builder
+            column_num: 0,
+        },
+        labeled_transitions: Vec::new(),
+        node_attributes: HashMap::new(),
+        captures: Vec::new(),
+        when: None,
+        fixed_address: false,
+        origin,
+    })
+}
+// Entry and exit events for every function that matches the pattern.
+// The nodes have fixed addresses as functions do not start or return
+// on a line of their own.
+fn function_nodes(erebor: &Erebor, function_pattern: &FunctionPattern) -> anyhow::Result<Vec<GraphNode>> {
+    let name_re = match &function_pattern.pattern {
+        Some(pattern) => Some(glob_to_regex(pattern, "")?),
+        None => None,
+    };
+    // Relative paths match the end of the path
+    let file_re = match &function_pattern.file {
+        Some(file) => Some(glob_to_regex(file, "(.*/)?")?),
+        None => None,
+    };
+    let mut nodes = Vec::new();
+    for (file_name, file_info) in &erebor.files {
+        if file_re.as_ref().map_or(false, |re| !re.is_match(&file_name.to_string_lossy())) {
+            continue;
+        }
+        for func in &file_info.functions {
+            if name_re.as_ref().map_or(false, |re| !re.is_match(&func.demangled_name)) {
+                continue;
+            }
+            // :: would be read as a module separator
+            let name = func.demangled_name.replace("::", ".");
+            let node = |suffix: &str, node_type: &str, address: usize, line_num: u32| GraphNode {
+                FQN: "".into(), // WILL BE REPLACED BY graph_builder
+                address,
+                module: function_pattern.module.clone(),
+                name: format!("{}{}", name, suffix),
+                node_type: node_type.into(),
+                location: LineLocation {
+                    file: file_name.clone(),
+                    line_num,
+                    column_num: 0,
+                },
+                labeled_transitions: Vec::new(),
+                node_attributes: HashMap::new(),
+                captures: Vec::new(),
+                when: None,
+                fixed_address: true,
+                origin: AnnotationOrigin::Functions,
+            };
+            nodes.push(node(FUNCTION_ENTRY_SUFFIX, "FunctionEntry", func.address, func.start_line));
+            for ret_addr in &func.return_addresses {
+                nodes.push(node(FUNCTION_EXIT_SUFFIX, "FunctionExit", *ret_addr, func.end_line));
+            }
+        }
+    }
+    if nodes.is_empty() {
+        log::warn!("No functions matched {:?}", function_pattern);
+    }
+    Ok(nodes)
+}
+// `*` matches any run of characters and `?` any single character
+fn glob_to_regex(glob: &str, prefix: &str) -> anyhow::Result<Regex> {
+    let mut re = format!("^{}", prefix);
+    for c in glob.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
 // TODO: prevent infinite recursion with a module having itself
 // as its parent
 pub fn name_to_fqn(name: &str, modules: &HashMap<String, GraphModule>) -> anyhow::Result<String> {
@@ -146,9 +477,9 @@ pub fn name_to_fqn(name: &str, modules: &HashMap<String, GraphModule>) -> anyhow
     Ok(ret)
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(tag = "type")]
-enum Annotation {
+pub(crate) enum Annotation {
     #[serde(rename = "module")]
     Module {
         name: String,
@@ -157,15 +488,36 @@ enum Annotation {
         comment: Option<String>,
     },
     #[serde(rename = "event")]
-    // {type:"flow", name:"module::self"}
-    Event { name: String },
+    // {type:"event", name:"module::self", capture:["var", "ptr->field"], when:"var > 0"}
+    Event {
+        name: String,
+        #[serde(default)]
+        capture: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        when: Option<String>,
+    },
     #[serde(rename = "flow")]
     // {type:"flow", name:"module::self"}
     Flow { name: String },
+    #[serde(rename = "functions")]
+    // {type:"functions", match:"parse_*", file:"parser.c", module:"parser"}
+    Functions {
+        #[serde(rename = "match")]
+        pattern: Option<String>,
+        file: Option<String>,
+        #[serde(default)]
+        module: String,
+    },
+    #[serde(rename = "span_begin")]
+    // {type:"span_begin", name:"module::self"}
+    SpanBegin { name: String },
+    #[serde(rename = "span_end")]
+    // {type:"span_end", name:"module::self"}
+    SpanEnd { name: String },
 }
 
-fn parse_line(line: &str) -> anyhow::Result<Option<Annotation>> {
-    let re = Regex::new(r"\[\[\{(.*)\}\]\]")?;
+pub(crate) fn parse_line(line: &str) -> anyhow::Result<Option<Annotation>> {
+    let re = Regex::new(ANNOTATION_PATTERN)?;
     let caps = re.captures(line);
     let Some(cap) = caps else {
         return Ok(None);
@@ -208,10 +560,99 @@ mod tests {
         let line = r#"[[{type:"event", name:"parent::pizza"}]]"#;
         let eq = Annotation::Event {
             name: "parent::pizza".into(),
+            capture: Vec::new(),
+            when: None,
+        };
+        assert_eq!(parse_line(line).unwrap(), Some(eq));
+    }
+    #[test]
+    fn event_capture_deserialize() {
+        let line = r#"[[{type:"event", name:"::pizza", capture:["size", "order->price"]}]]"#;
+        let eq = Annotation::Event {
+            name: "::pizza".into(),
+            capture: vec!["size".into(), "order->price".into()],
+            when: None,
         };
         assert_eq!(parse_line(line).unwrap(), Some(eq));
     }
     #[test]
+    fn event_when_deserialize() {
+        let line = r#"[[{type:"event", name:"alloc::big", when:"size > 4096"}]]"#;
+        let eq = Annotation::Event {
+            name: "alloc::big".into(),
+            capture: Vec::new(),
+            when: Some("size > 4096".into()),
+        };
+        assert_eq!(parse_line(line).unwrap(), Some(eq));
+    }
+    #[test]
+    fn span_deserialize() {
+        let line = r#"    // [[{type:"span_begin", name:"server::request"}]]"#;
+        let eq = Annotation::SpanBegin {
+            name: "server::request".into(),
+        };
+        assert_eq!(parse_line(line).unwrap(), Some(eq));
+        let line = r#"[[{type:"span_end", name:"server::request"}]]"#;
+        let eq = Annotation::SpanEnd {
+            name: "server::request".into(),
+        };
+        assert_eq!(parse_line(line).unwrap(), Some(eq));
+    }
+    #[test]
+    fn functions_deserialize() {
+        let line = r#"[[{type:"functions", match:"parse_*", module:"parser"}]]"#;
+        let eq = Annotation::Functions {
+            pattern: Some("parse_*".into()),
+            file: None,
+            module: "parser".into(),
+        };
+        assert_eq!(parse_line(line).unwrap(), Some(eq));
+        let line = r#"[[{type:"functions", file:"src/*.c"}]]"#;
+        let eq = Annotation::Functions {
+            pattern: None,
+            file: Some("src/*.c".into()),
+            module: "".into(),
+        };
+        assert_eq!(parse_line(line).unwrap(), Some(eq));
+    }
+    #[test]
+    fn globs() {
+        let re = glob_to_regex("parse_*", "").unwrap();
+        assert!(re.is_match("parse_header"));
+        assert!(!re.is_match("do_parse_header"));
+        let re = glob_to_regex("src/?.c", "(.*/)?").unwrap();
+        assert!(re.is_match("/home/me/project/src/a.c"));
+        assert!(re.is_match("src/a.c"));
+        assert!(!re.is_match("/home/me/project/src/ab.c"));
+        assert!(!re.is_match("/home/me/project/notsrc/a.c"));
+    }
+    #[test]
+    fn sidecar_deserialize() {
+        let text = r#"[
+            {type:"module", name:"alloc"},
+            {type:"event", name:"alloc::big", line:"malloc/malloc.c:3021", when:"bytes > 4096"},
+            {type:"span_begin", name:"alloc::free", function:"__libc_free"},
+            {type:"flow", name:"alloc::raw", address:0x1149},
+        ]"#;
+        let entries: Vec<SidecarAnnotation> = json5::from_str(text).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[1],
+            SidecarAnnotation {
+                annotation: Annotation::Event {
+                    name: "alloc::big".into(),
+                    capture: Vec::new(),
+                    when: Some("bytes > 4096".into()),
+                },
+                line: Some("malloc/malloc.c:3021".into()),
+                function: None,
+                address: None,
+            }
+        );
+        assert_eq!(entries[2].function.as_deref(), Some("__libc_free"));
+        assert_eq!(entries[3].address, Some(0x1149));
+    }
+    #[test]
     fn flow_deserialize() {
         let line = r#"[[{type:"flow", name:"::pizza"}]]"#;
         let eq = Annotation::Flow {
@@ -228,6 +669,7 @@ mod tests {
                 name: "".into(),
                 parent: None,
                 module_attributes: HashMap::new(),
+                origin: AnnotationOrigin::New,
             },
         );
         modules.insert(
@@ -236,6 +678,7 @@ mod tests {
                 name: "animal".into(),
                 parent: Some("".into()),
                 module_attributes: HashMap::new(),
+                origin: AnnotationOrigin::New,
             },
         );
         modules.insert(
@@ -244,6 +687,7 @@ mod tests {
                 name: "dog".into(),
                 parent: Some("animal".into()),
                 module_attributes: HashMap::new(),
+                origin: AnnotationOrigin::New,
             },
         );
         assert!(name_to_fqn("error", &modules).is_err());
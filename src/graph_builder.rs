@@ -1,52 +1,98 @@
 use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
 use std::io::Write;
-use std::process::Command;
+use std::path::PathBuf;
 
 use dot_writer::{Attributes, DotWriter, Scope};
-use gml_parser::GMLObject;
 use librr_rs::BinaryInterface;
-use std::fs::File;
+use serde::{Deserialize, Serialize};
 
+use crate::annotation_writer::SourceSnapshot;
+use crate::capture::{self, ReplayAccess};
+use crate::condition;
 use crate::erebor::Erebor;
 use crate::file_parsing;
-use crate::shared_structs::{GraphModule, Settings};
+use crate::jobs::JobProgress;
+use crate::shared_structs::{
+    ApiError, ApiErrorKind, CapturedValue, ExportedEdge, ExportedNode, GraphExport, GraphModule,
+    JobPhase, Settings, Span,
+};
+use crate::simulation::Simulation;
+use crate::spans;
+use crate::synoptic::{self, InferredEdge, InferredGraph, InferredNode};
 use crate::{
     address_recorder::AddressRecorder,
     // query::node::TimeRange,
-    shared_structs::{GraphNode, TimeStamp},
+    shared_structs::{GraphNode, ThreadID, TimeStamp},
 };
 use librr_rs::*;
 
-// TODO
-// Implement a clever prepared scheme
-// that saves prior work.
-//
-// This implementation is comically inefficient
-//
+// Saved in the recording directory so that reopening a trace
+// with unchanged annotations does not replay the whole program.
+pub const EVENT_CACHE_FILE_NAME: &str = "event_cache.json";
+// Bump when the layout of EventCache or AddressRecorder changes
+const EVENT_CACHE_VERSION: u32 = 6;
+// Past this many frame times with both old and new events
+// a full replay is faster than one replay per frame time
+const MAX_RERECORDED_FRAME_TIMES: usize = 32;
+
+// What is read out of the replay at an event address
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct RecordedEvent {
+    captures: Vec<String>,
+    when: Option<String>,
+}
+type RecordedEvents = HashMap<usize, RecordedEvent>;
+
+#[derive(Serialize, Deserialize)]
+struct EventCache {
+    version: u32,
+    // Sorted by address
+    events: Vec<(usize, RecordedEvent)>,
+    address_recorder: AddressRecorder,
+}
+
+// Cloned by jobs so the graph can still be read while they prepare
+#[derive(Clone)]
 pub struct GraphBuilder {
     address_recorder: AddressRecorder,
+    // None until something has been recorded
+    recorded_events: Option<RecordedEvents>,
+    save_directory: PathBuf,
     is_prepared: bool,
-    gml_graph: Option<gml_parser::Graph>,
+    // Give each thread its own trace when inferring the graph
+    // instead of one interleaved trace
+    partition_by_thread: bool,
+    inferred_graph: Option<InferredGraph>,
+    // Bumped every time the graph changes
+    pub version: usize,
     pub modules: HashMap<String, GraphModule>,
     pub synoptic_nodes: HashMap<usize, GraphNode>,
     pub nodes: HashMap<usize, GraphNode>,
+    // Source files as they were when the annotations were read
+    pub sources: HashMap<PathBuf, SourceSnapshot>,
 }
 
 // Stablize negative f------ trait impls
 // impl !Send for GraphBuilder
 
 impl GraphBuilder {
-    pub fn new(max_ft: usize) -> Self {
+    pub fn new(max_ft: usize, save_directory: PathBuf) -> Self {
         Self {
             address_recorder: AddressRecorder::new(max_ft),
+            recorded_events: None,
+            save_directory,
             nodes: HashMap::new(),
             synoptic_nodes: HashMap::new(),
             is_prepared: false,
-            gml_graph: None,
+            partition_by_thread: Settings::default().partition_by_thread,
+            inferred_graph: None,
+            version: 0,
             modules: HashMap::new(),
+            sources: HashMap::new(),
         }
     }
     pub fn update_raw_nodes(
@@ -62,35 +108,55 @@ impl GraphBuilder {
             node.FQN = file_parsing::name_to_fqn(
                 &format!("{}::{}", &node.module, &node.name),
                 &self.modules,
-            )?;
+            )
+            .map_err(|e| annotation_error(node, e))?;
 
-            // Should only impose minor perf pentalty on future runs
-            // as the naive_line_num will be accurate on future executions
-            // (final_offset will be 0)
-            let naive_line_num = node.location.line_num;
-            let file_info = erebor.files.get(&node.location.file).ok_or_else(|| {
-                anyhow::anyhow!(
-                    "File name for {} was not inside of the DWARF data for the binary. ",
-                    node.FQN
-                )
-            })?;
-            let mut event_addr = None;
-            let mut final_offset = 0;
-            'addr_search: for offset in 0..1000 {
-                let addrs = file_info.lines.get(&((naive_line_num + offset) as u32));
-                if let Some(addrs) = addrs {
-                    if addrs.len() > 0 {
-                        event_addr = Some(addrs.first().unwrap().clone());
-                        final_offset = offset;
-                        break 'addr_search;
+            if !(node.fixed_address && node.address != 0) {
+                // Should only impose minor perf pentalty on future runs
+                // as the naive_line_num will be accurate on future executions
+                // (final_offset will be 0)
+                let naive_line_num = node.location.line_num;
+                node.location.file = erebor.source_file(&node.location.file).ok_or_else(|| {
+                    annotation_error(
+                        node,
+                        format!(
+                            "File name for {} was not inside of the DWARF data for the binary. ",
+                            node.FQN
+                        ),
+                    )
+                })?;
+                let file_info = &erebor.files[&node.location.file];
+                let mut event_addr = None;
+                let mut final_offset = 0;
+                'addr_search: for offset in 0..1000 {
+                    let addrs = file_info.lines.get(&((naive_line_num + offset) as u32));
+                    if let Some(addrs) = addrs {
+                        if addrs.len() > 0 {
+                            event_addr = Some(addrs.first().unwrap().clone());
+                            final_offset = offset;
+                            break 'addr_search;
+                        }
                     }
                 }
+                let Some(event_addr) = event_addr else {
+                    return Err(annotation_error(
+                        node,
+                        format!("Unable to find an address for the {} event annotation", node.FQN),
+                    ));
+                };
+                node.address = event_addr;
+                node.location.line_num = (final_offset + naive_line_num) as u32;
+            }
+            for expression in &node.captures {
+                capture::parse_capture(expression).map_err(|e| {
+                    annotation_error(node, format!("{} in the {} event annotation", e, node.FQN))
+                })?;
+            }
+            if let Some(when) = &node.when {
+                condition::parse_condition(when).map_err(|e| {
+                    annotation_error(node, format!("{} in the {} event annotation", e, node.FQN))
+                })?;
             }
-            let Some(event_addr) = event_addr else {
-                return Err(anyhow::anyhow!("Unable to find an address for the {} event annotation", node.FQN));
-            };
-            node.address = event_addr;
-            node.location.line_num = (final_offset + naive_line_num) as u32;
         }
         self.nodes.clear();
         for (_, node) in nodes {
@@ -111,16 +177,49 @@ impl GraphBuilder {
         if !self.is_prepared {
             return Ok(None);
         }
+        self.set_partition_by_thread(settings.partition_by_thread)?;
 
-        let data = self.gml_to_dot_str(
-            self.gml_graph
+        let data = self.graph_to_dot_str(
+            self.inferred_graph
                 .as_ref()
-                .expect("gml graph was None during get_graph_as_dot"),
+                .expect("inferred graph was None during get_graph_as_dot"),
             settings,
             erebor,
         )?;
         Ok(Some(data))
     }
+    pub fn get_graph_export(&self) -> anyhow::Result<Option<GraphExport>> {
+        if !self.is_prepared {
+            return Ok(None);
+        }
+        let inferred_graph = self
+            .inferred_graph
+            .as_ref()
+            .expect("inferred graph was None during get_graph_export");
+        let nodes = inferred_graph
+            .nodes
+            .iter()
+            .map(|node| ExportedNode {
+                id: node.id,
+                label: node.label.clone(),
+                node: self.synoptic_nodes.get(&(node.id as usize)).cloned(),
+            })
+            .collect();
+        let edges = inferred_graph
+            .edges
+            .iter()
+            .map(|edge| ExportedEdge {
+                source: edge.source,
+                target: edge.target,
+                probability: Some(edge.probability),
+            })
+            .collect();
+        Ok(Some(GraphExport {
+            modules: self.modules.clone(),
+            nodes,
+            edges,
+        }))
+    }
     //TODO: This code is heavily flawed and was written hastily in order to get something
     //written
     // TODO : This code is also very fragile and /requires/ tests
@@ -128,130 +227,423 @@ impl GraphBuilder {
     // 0 => rerun all
     // 1 => rerun synoptic but not program
     // 2 => Dont rerun
+    //
+    // A run_level of 0 only replays what is needed to bring the
+    // recording up to date with the current nodes.
     pub fn prepare(
         &mut self,
         bin_interface: &mut BinaryInterface,
+        erebor: &Erebor,
         run_level: u32,
+        progress: &JobProgress,
     ) -> anyhow::Result<()> {
-        // bin_interface.pin_mut().restart_from_event(0);
         if run_level == 0 {
-            let cont = GdbContAction {
-                type_: GdbActionType::ACTION_CONTINUE,
-                target: bin_interface.get_current_thread(),
-                signal_to_deliver: 0,
-            };
-            let step = GdbContAction {
-                type_: GdbActionType::ACTION_STEP,
-                target: bin_interface.get_current_thread(),
-                signal_to_deliver: 0,
-            };
-
-            for node in self.nodes.values() {
-                // dbg!(&node);
-                if node.address == 0 {
-                    anyhow::bail!(
-                        "Node address for {} is 0. This should never happen.",
-                        &node.FQN
-                    );
-                }
-                bin_interface.pin_mut().set_sw_breakpoint(node.address, 1);
+            progress.set_phase(JobPhase::Replay);
+            if self.recorded_events.is_none() && self.load_event_cache() {
+                log::info!("Loaded the cached event recording");
             }
-            dbg!(self.nodes.len());
+            self.update_recording(bin_interface, erebor, progress)?;
+        }
+        if run_level == 0 || run_level == 1 {
+            progress.check_cancelled()?;
+            progress.set_phase(JobPhase::Synoptic);
+            self.infer_graph()?;
+        }
 
-            let mut opened_frame_time: i64 = bin_interface.current_frame_time();
-            self.address_recorder.clear();
+        self.version += 1;
+        self.is_prepared = true;
+        Ok(())
+    }
+    pub fn max_frame_time(&self) -> usize {
+        self.address_recorder.max_frame_time()
+    }
+    // Re-infers the graph if the partitioning changed
+    pub fn set_partition_by_thread(&mut self, partition_by_thread: bool) -> anyhow::Result<()> {
+        if self.partition_by_thread == partition_by_thread {
+            return Ok(());
+        }
+        self.partition_by_thread = partition_by_thread;
+        if self.is_prepared {
+            self.infer_graph()?;
+            self.version += 1;
+        }
+        Ok(())
+    }
+    // Turn the recorded addresses into event names and infer the graph
+    fn infer_graph(&mut self) -> anyhow::Result<()> {
+        let traces: Vec<Vec<usize>> = if self.partition_by_thread {
             self.address_recorder
-                .reset_ft_for_writing(opened_frame_time as usize);
+                .get_addresses_by_thread()
+                .into_values()
+                .collect()
+        } else {
+            vec![self.address_recorder.get_all_addresses()?.collect()]
+        };
+        let traces = traces
+            .iter()
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .map(|addr| {
+                        self.nodes
+                            .get(addr)
+                            .map(|node| node.FQN.as_str())
+                            .ok_or_else(|| {
+                                anyhow::anyhow!("Recorded address {:#x} has no event", addr)
+                            })
+                    })
+                    .collect::<anyhow::Result<Vec<&str>>>()
+            })
+            .collect::<anyhow::Result<Vec<Vec<&str>>>>()?;
+        let graph = synoptic::infer(&traces, synoptic::DEFAULT_MAX_K)?;
 
-            let mut signal = 5;
-            while signal == 5 {
-                let rip = bin_interface
-                    .get_register(GdbRegister::DREG_RIP, bin_interface.get_current_thread())
-                    .to_usize();
-                let current_ft = bin_interface.current_frame_time();
-                if current_ft != opened_frame_time {
-                    self.address_recorder.finished_writing_ft();
-                    opened_frame_time = current_ft;
-                    self.address_recorder
-                        .reset_ft_for_writing(opened_frame_time as usize);
+        self.build_synoptic_nodes(&graph);
+        self.inferred_graph = Some(graph);
+        Ok(())
+    }
+    // Diff the nodes against the events that were recorded.
+    // Removed events are dropped from the recording and only the
+    // added events are replayed. Frame times where old and new
+    // events were both hit are recorded again with all of them
+    // so that the hits stay in order. An event whose captures or
+    // condition changed counts as removed and added.
+    fn update_recording(
+        &mut self,
+        bin_interface: &mut BinaryInterface,
+        erebor: &Erebor,
+        progress: &JobProgress,
+    ) -> anyhow::Result<()> {
+        for node in self.nodes.values() {
+            if node.address == 0 {
+                anyhow::bail!(
+                    "Node address for {} is 0. This should never happen.",
+                    &node.FQN
+                );
+            }
+        }
+        let events: RecordedEvents = self
+            .nodes
+            .iter()
+            .map(|(addr, node)| {
+                let event = RecordedEvent {
+                    captures: node.captures.clone(),
+                    when: node.when.clone(),
+                };
+                (*addr, event)
+            })
+            .collect();
+        match self.recorded_events.take() {
+            Some(recorded) if recorded == events => {
+                self.recorded_events = Some(recorded);
+                return Ok(());
+            }
+            Some(recorded) => {
+                self.address_recorder
+                    .retain_addresses(|addr| events.get(&addr) == recorded.get(&addr));
+                let added: RecordedEvents = events
+                    .iter()
+                    .filter(|(addr, event)| recorded.get(addr) != Some(event))
+                    .map(|(addr, event)| (*addr, event.clone()))
+                    .collect();
+                if !added.is_empty() {
+                    let mut added_recorder =
+                        AddressRecorder::new(self.address_recorder.max_frame_time());
+                    Self::record_hits(
+                        bin_interface,
+                        erebor,
+                        &added,
+                        &mut added_recorder,
+                        None,
+                        progress,
+                    )?;
+                    let conflicts = self.address_recorder.merge_from(&added_recorder);
+                    log::debug!(
+                        "Recorded {} added events, {} frame times to re-record",
+                        added.len(),
+                        conflicts.len()
+                    );
+                    if conflicts.len() > MAX_RERECORDED_FRAME_TIMES {
+                        // Cheaper to replay everything once
+                        let mut session = Simulation::open_bin_interface(&self.save_directory, 0);
+                        self.address_recorder.clear();
+                        Self::record_hits(
+                            &mut session,
+                            erebor,
+                            &events,
+                            &mut self.address_recorder,
+                            None,
+                            progress,
+                        )?;
+                    } else {
+                        for frame_time in conflicts {
+                            let mut session = Simulation::open_bin_interface(
+                                &self.save_directory,
+                                frame_time.saturating_sub(1) as i64,
+                            );
+                            Self::record_hits(
+                                &mut session,
+                                erebor,
+                                &events,
+                                &mut self.address_recorder,
+                                Some(frame_time),
+                                progress,
+                            )?;
+                        }
+                    }
                 }
-                // serious problems about efficiently telling if this is an address of a node
-                // or of a timestamp
-                if self.nodes.contains_key(&rip) {
-                    self.address_recorder.insert_address(rip);
+            }
+            None => {
+                log::debug!("Recording {} events", events.len());
+                self.address_recorder.clear();
+                Self::record_hits(
+                    bin_interface,
+                    erebor,
+                    &events,
+                    &mut self.address_recorder,
+                    None,
+                    progress,
+                )?;
+            }
+        }
+        self.recorded_events = Some(events);
+        // A missing cache only costs time so don't fail the prepare
+        if let Err(e) = self.save_event_cache() {
+            log::warn!("Unable to save the event cache: {}", e);
+        }
+        Ok(())
+    }
+    // Continue the replay with breakpoints on the events until the
+    // program exits, recording every hit whose condition holds along
+    // with its captures. With only_frame_time set, only the hits in
+    // that frame time are recorded.
+    fn record_hits(
+        bin_interface: &mut BinaryInterface,
+        erebor: &Erebor,
+        events: &RecordedEvents,
+        recorder: &mut AddressRecorder,
+        only_frame_time: Option<usize>,
+        progress: &JobProgress,
+    ) -> anyhow::Result<()> {
+        let mut conditions = HashMap::new();
+        for (addr, event) in events {
+            if let Some(when) = &event.when {
+                conditions.insert(*addr, condition::parse_condition(when)?);
+            }
+        }
+        // Addresses whose condition failed to evaluate, to warn only once
+        let mut failed_conditions = HashSet::new();
+        let cont = GdbContAction {
+            type_: GdbActionType::ACTION_CONTINUE,
+            target: bin_interface.get_current_thread(),
+            signal_to_deliver: 0,
+        };
+        for addr in events.keys() {
+            bin_interface.pin_mut().set_sw_breakpoint(*addr, 1);
+        }
 
-                    // meaning there is a breakpoint at rip
-                    // so we have to step over it when there is no
-                    // breakpoint
-                    bin_interface.pin_mut().remove_sw_breakpoint(rip, 1);
-                    signal = bin_interface.pin_mut().continue_forward(step).unwrap();
-                    if signal != 5 {
-                        break;
+        let mut opened_frame_time: Option<usize> = None;
+        // Hits of each address in the opened frame time, including the
+        // ones whose condition failed, as gdb counts them when replaying
+        let mut hits: HashMap<usize, usize> = HashMap::new();
+        let mut signal = 5;
+        while signal == 5 {
+            // The thread that stopped
+            let thread = bin_interface.get_current_thread();
+            let rip = bin_interface
+                .get_register(GdbRegister::DREG_RIP, thread)
+                .to_usize();
+            let current_ft = bin_interface.current_frame_time() as usize;
+            if only_frame_time.map_or(false, |ft| current_ft > ft) {
+                break;
+            }
+            progress.set_frame_time(current_ft);
+            if progress.is_cancelled() {
+                break;
+            }
+            let recording = only_frame_time.map_or(true, |ft| current_ft == ft);
+            if recording && opened_frame_time != Some(current_ft) {
+                if opened_frame_time.is_some() {
+                    recorder.finished_writing_ft();
+                }
+                opened_frame_time = Some(current_ft);
+                recorder.reset_ft_for_writing(current_ft);
+                hits.clear();
+            }
+            // serious problems about efficiently telling if this is an address of a node
+            // or of a timestamp
+            if let Some(event) = events.get(&rip) {
+                let instance = hits.entry(rip).or_insert(0);
+                *instance += 1;
+                let instance = *instance;
+                let mut target = ReplayAccess {
+                    bin_interface: &*bin_interface,
+                    thread,
+                };
+                // A condition that cannot be evaluated counts as false
+                let passes = match conditions.get(&rip) {
+                    Some(condition) if recording => {
+                        match condition.evaluate(erebor, &mut target, rip) {
+                            Ok(passes) => passes,
+                            Err(e) => {
+                                if failed_conditions.insert(rip) {
+                                    log::warn!("Unable to evaluate the condition at {:#x}: {}", rip, e);
+                                }
+                                false
+                            }
+                        }
+                    }
+                    _ => true,
+                };
+                if recording && passes {
+                    recorder.insert_hit(rip, thread.tid, instance);
+                    if !event.captures.is_empty() {
+                        let values = event
+                            .captures
+                            .iter()
+                            .map(|expression| CapturedValue {
+                                expression: expression.clone(),
+                                value: capture::capture_value(erebor, &mut target, rip, expression),
+                            })
+                            .collect();
+                        recorder.insert_captures(values);
                     }
-                    bin_interface.pin_mut().set_sw_breakpoint(rip, 1);
                 }
 
-                signal = bin_interface.pin_mut().continue_forward(cont).unwrap();
+                // meaning there is a breakpoint at rip
+                // so we have to step over it when there is no
+                // breakpoint
+                let step = GdbContAction {
+                    type_: GdbActionType::ACTION_STEP,
+                    target: thread,
+                    signal_to_deliver: 0,
+                };
+                bin_interface.pin_mut().remove_sw_breakpoint(rip, 1);
+                signal = bin_interface.pin_mut().continue_forward(step).unwrap();
+                if signal != 5 {
+                    break;
+                }
+                bin_interface.pin_mut().set_sw_breakpoint(rip, 1);
             }
-            self.address_recorder.finished_writing_ft();
 
-            // Remove all set swbreakpoints to not alter internal state of machine
-            for node in self.nodes.values() {
-                bin_interface
-                    .pin_mut()
-                    .remove_sw_breakpoint(node.address, 1);
-            }
+            signal = bin_interface.pin_mut().continue_forward(cont).unwrap();
         }
-        // Record locations in test.log, run synoptic, then read the output file
-        if run_level == 0 || run_level == 1 {
-            let base = ".";
-            // make sure to delete the out.gml file so we don't use stale data
-            // intentionally dont fail if the file doesn't exist
-            let _ = std::fs::remove_file(format!("{}/synoptic/shared/out.gml", &base));
-
-            let addresses = self.address_recorder.get_all_addresses().unwrap();
-            //let it: TupleWindows<AddrIter, (usize,usize)> = addresses.tuple_windows();
-            let node_names = addresses.map(|addr| &self.nodes.get(&addr).unwrap().FQN);
-            let mut output = File::create(format!("{}/synoptic/shared/test.log", &base))?;
-            for name in node_names {
-                writeln!(output, "{}", name)?;
-            }
-            let _out = Command::new(format!("{}/synoptic/run.sh", &base)).output()?;
-            let gml_data = std::fs::read_to_string(format!("{}/synoptic/shared/out.gml", &base))
-                .unwrap_or("graph [\n]".into());
-            let root = GMLObject::from_str(&gml_data)?;
-            let graph = gml_parser::Graph::from_gml(root)?;
-
-            self.build_synoptic_nodes(&graph);
-            self.gml_graph = Some(graph);
+        if opened_frame_time.is_some() {
+            recorder.finished_writing_ft();
         }
 
-        self.is_prepared = true;
+        // Remove all set swbreakpoints to not alter internal state of machine
+        for addr in events.keys() {
+            bin_interface.pin_mut().remove_sw_breakpoint(*addr, 1);
+        }
+        progress.check_cancelled()
+    }
+    // Sorted so the cache does not depend on HashMap ordering
+    fn sorted_events(events: &RecordedEvents) -> Vec<(usize, RecordedEvent)> {
+        let mut events: Vec<(usize, RecordedEvent)> = events
+            .iter()
+            .map(|(addr, event)| (*addr, event.clone()))
+            .collect();
+        events.sort();
+        events
+    }
+    // Load the recording saved by a previous run, whatever annotations
+    // it was made with. update_recording then only replays the difference.
+    // Any problem reading it is treated as if there was no cache.
+    fn load_event_cache(&mut self) -> bool {
+        let path = self.save_directory.join(EVENT_CACHE_FILE_NAME);
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return false,
+        };
+        let cache: EventCache = match serde_json::from_reader(std::io::BufReader::new(file)) {
+            Ok(cache) => cache,
+            Err(e) => {
+                log::warn!("Ignoring unreadable event cache {}: {}", path.to_string_lossy(), e);
+                return false;
+            }
+        };
+        if cache.version != EVENT_CACHE_VERSION
+            || cache.address_recorder.max_frame_time() != self.address_recorder.max_frame_time()
+        {
+            log::warn!("Ignoring stale event cache {}", path.to_string_lossy());
+            return false;
+        }
+        self.address_recorder = cache.address_recorder;
+        self.recorded_events = Some(cache.events.into_iter().collect());
+        true
+    }
+    fn save_event_cache(&self) -> anyhow::Result<()> {
+        let events = Self::sorted_events(
+            self.recorded_events
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Nothing has been recorded"))?,
+        );
+        let cache = EventCache {
+            version: EVENT_CACHE_VERSION,
+            events,
+            address_recorder: self.address_recorder.clone(),
+        };
+        // Write then rename so an interrupted save never leaves a
+        // truncated cache behind
+        let path = self.save_directory.join(EVENT_CACHE_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, &cache)?;
+        writer.flush()?;
+        std::fs::rename(&tmp_path, &path)?;
         Ok(())
     }
     // TODO ensure this fits into the graph rather than just grabbing the
     // address
-    pub fn get_addr_occurrences(&self, synoptic_id: usize) -> Vec<TimeStamp> {
-        let addr = &self.synoptic_nodes[&synoptic_id].address;
-        self.address_recorder.get_addr_occurrences(*addr)
+    pub fn get_addr_occurrences(
+        &self,
+        synoptic_id: usize,
+    ) -> Result<Vec<(TimeStamp, Vec<CapturedValue>)>, ApiError> {
+        // The initial and terminal nodes have no address
+        let node = self.synoptic_nodes.get(&synoptic_id).ok_or_else(|| {
+            ApiError::new(
+                ApiErrorKind::NotFound,
+                format!("No node with an address has id {}", synoptic_id),
+            )
+        })?;
+        Ok(self
+            .address_recorder
+            .get_addr_occurrences_with_captures(node.address))
+    }
+    pub fn get_thread_lanes(&self) -> BTreeMap<ThreadID, Vec<TimeStamp>> {
+        self.address_recorder.get_thread_lanes()
     }
-    fn build_synoptic_nodes(&mut self, gml_graph: &gml_parser::Graph) {
+    pub fn get_spans(&self, times: &HashMap<i64, u128>) -> Vec<Span> {
+        let span_of = |addr: usize| {
+            let node = self.nodes.get(&addr)?;
+            let (suffix, is_begin) = match node.node_type.as_str() {
+                "SpanBegin" => (file_parsing::SPAN_BEGIN_SUFFIX, true),
+                "SpanEnd" => (file_parsing::SPAN_END_SUFFIX, false),
+                _ => return None,
+            };
+            // Renamed nodes are paired by their full name
+            let name = node.FQN.strip_suffix(suffix).unwrap_or(&node.FQN);
+            Some((name.to_string(), is_begin))
+        };
+        spans::pair_spans(self.address_recorder.get_thread_lanes(), span_of, times)
+    }
+    fn build_synoptic_nodes(&mut self, inferred_graph: &InferredGraph) {
         self.synoptic_nodes.clear();
-        'outer: for gml_node in &gml_graph.nodes {
+        'outer: for inferred_node in &inferred_graph.nodes {
             for (_, my_node) in &self.nodes {
-                if my_node.FQN == gml_node.label.clone().unwrap() {
+                if my_node.FQN == inferred_node.label {
                     self.synoptic_nodes
-                        .insert(gml_node.id as usize, my_node.clone());
+                        .insert(inferred_node.id as usize, my_node.clone());
                     continue 'outer;
                 }
             }
         }
     }
     fn get_synoptic_node_groups<'a>(
-        module_nodes: &'a Vec<&'a gml_parser::Node>,
-        edges: &Vec<gml_parser::Edge>,
-    ) -> Vec<Vec<&'a gml_parser::Node>> {
-        let mut groups: Vec<Vec<&gml_parser::Node>> = Vec::new();
+        module_nodes: &'a Vec<&'a InferredNode>,
+        edges: &Vec<InferredEdge>,
+    ) -> Vec<Vec<&'a InferredNode>> {
+        let mut groups: Vec<Vec<&InferredNode>> = Vec::new();
         let mut outgoing_pairs = Vec::new(); // Node -> id
         let mut incoming_pairs = Vec::new(); // id -> Node
         for node in module_nodes {
@@ -339,8 +731,8 @@ impl GraphBuilder {
         &self,
         parent_name: Option<&str>,
         parent_scope: &mut Scope,
-        nodes: &Vec<gml_parser::Node>,
-        edges: &Vec<gml_parser::Edge>,
+        nodes: &Vec<InferredNode>,
+        edges: &Vec<InferredEdge>,
         erebor: &Erebor,
         settings: &Settings,
         collapsed_module_map: &Vec<(Vec<i64>, i64)>,
@@ -355,8 +747,8 @@ impl GraphBuilder {
         let mut module_nodes = Vec::new();
         let mut module_fqns = HashSet::new();
         for node in nodes {
-            let label = &node.label.clone().unwrap();
-            if label == "INITIAL" || label == "TERMINAL" {
+            let label = &node.label;
+            if label == synoptic::INITIAL_LABEL || label == synoptic::TERMINAL_LABEL {
                 if parent_name == None {
                     parent_scope
                         .node_named(format!("N{}", node.id))
@@ -425,7 +817,7 @@ impl GraphBuilder {
                 .set_style(dot_writer::Style::Dashed);
 
             for node in group {
-                let label = &node.label.clone().unwrap();
+                let label = &node.label;
                 let is_selected = Some(node.id as usize) == settings.selected_node_id;
                 let color: dot_writer::Color = if is_selected {
                     dot_writer::Color::Red
@@ -548,9 +940,9 @@ impl GraphBuilder {
         }
     }
 
-    fn gml_to_dot_str(
+    fn graph_to_dot_str(
         &self,
-        gml_graph: &gml_parser::Graph,
+        inferred_graph: &InferredGraph,
         settings: &Settings,
         erebor: &Erebor,
     ) -> anyhow::Result<String> {
@@ -565,15 +957,15 @@ impl GraphBuilder {
             self.create_node_recursive(
                 None,
                 &mut digraph,
-                &gml_graph.nodes,
-                &gml_graph.edges,
+                &inferred_graph.nodes,
+                &inferred_graph.edges,
                 erebor,
                 settings,
                 &collapsed_module_map,
             );
             let mut edge_vec = HashSet::new();
-            'edge: for edge in &gml_graph.edges {
-                // for node in &gml_graph.nodes{
+            'edge: for edge in &inferred_graph.edges {
+                // for node in &inferred_graph.nodes{
                 //     if node.id == edge.source && node.label == Some("INITIAL".into()) {
                 //         continue 'edge;
                 //     }
@@ -612,14 +1004,7 @@ impl GraphBuilder {
                         format!("{}{}", target_prefix, target),
                     )
                     .attributes();
-                if let Some(label) = edge.label.clone() {
-                    let val = &label[3..];
-                    // dbg!(&val);
-                    let val = val.parse::<f32>();
-                    if let Ok(val) = val {
-                        attribs.set_pen_width(val * 5. + 1.5);
-                    }
-                }
+                attribs.set_pen_width(edge.probability * 5. + 1.5);
                 if Some(source as usize) == settings.selected_node_id {
                     attribs.set_color(dot_writer::Color::Red);
                     // attribs.set_rank(dot_writer::Rank::Max);
@@ -632,3 +1017,7 @@ impl GraphBuilder {
         Ok(String::from_utf8(output_bytes)?)
     }
 }
+// Keeps the location of the annotation so the UI can point at it
+fn annotation_error(node: &GraphNode, message: impl std::fmt::Display) -> anyhow::Error {
+    ApiError::annotation(message.to_string(), node.location.clone()).into()
+}
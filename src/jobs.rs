@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::shared_structs::{
+    ApiError, ApiErrorKind, JobID, JobPhase, JobState, JobStatus, TraceID,
+};
+
+// How far a job is, written by the thread running it and read by
+// /job_status. Preparing a graph outside of a job (at startup, when
+// restoring a session or exporting) uses JobProgress::default().
+#[derive(Debug, Default)]
+pub struct JobProgress {
+    phase: Mutex<JobPhase>,
+    frame_time: AtomicUsize,
+    cancelled: AtomicBool,
+}
+impl JobProgress {
+    pub fn set_phase(&self, phase: JobPhase) {
+        *self.phase.lock().unwrap_or_else(PoisonError::into_inner) = phase;
+    }
+    pub fn phase(&self) -> JobPhase {
+        *self.phase.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    // The frame time the replay has reached
+    pub fn set_frame_time(&self, frame_time: usize) {
+        self.frame_time.store(frame_time, Ordering::Relaxed);
+    }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+    // Called between steps so a cancelled job stops soon after
+    pub fn check_cancelled(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            anyhow::bail!("The job was cancelled");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: JobID,
+    pub trace_id: TraceID,
+    max_frame_time: usize,
+    pub progress: JobProgress,
+    // State and error once it is not running
+    outcome: Mutex<(JobState, Option<ApiError>)>,
+}
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.outcome.lock().unwrap_or_else(PoisonError::into_inner).0 == JobState::Running
+    }
+    pub fn finish(&self, result: Result<(), ApiError>) {
+        let outcome = match result {
+            Ok(()) => (JobState::Done, None),
+            // The error is only how the job noticed
+            Err(_) if self.progress.is_cancelled() => (JobState::Cancelled, None),
+            Err(err) => (JobState::Failed, Some(err)),
+        };
+        *self.outcome.lock().unwrap_or_else(PoisonError::into_inner) = outcome;
+    }
+    pub fn status(&self) -> JobStatus {
+        let (state, error) = self.outcome.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        let phase = self.progress.phase();
+        let frame_time = self.progress.frame_time.load(Ordering::Relaxed);
+        let percent = if state == JobState::Done || phase != JobPhase::Replay {
+            100.0
+        } else {
+            (100.0 * frame_time as f64 / self.max_frame_time.max(1) as f64).min(100.0)
+        };
+        JobStatus {
+            job_id: self.id,
+            trace_id: self.trace_id,
+            state,
+            phase,
+            percent,
+            error,
+        }
+    }
+}
+
+// Every job started by this server
+#[derive(Default)]
+pub struct Jobs {
+    next_id: JobID,
+    jobs: HashMap<JobID, Arc<Job>>,
+}
+impl Jobs {
+    // Anything that replaces the graph of a trace has to wait for its
+    // job. It would be overwritten when the job finishes.
+    pub fn check_idle(&self, trace_id: TraceID) -> Result<(), ApiError> {
+        match self
+            .jobs
+            .values()
+            .find(|job| job.trace_id == trace_id && job.is_running())
+        {
+            Some(running) => Err(ApiError::new(
+                ApiErrorKind::Conflict,
+                format!("Trace {} is busy with job {}", trace_id, running.id),
+            )),
+            None => Ok(()),
+        }
+    }
+    // One job per trace at a time: a second would prepare from the
+    // nodes of before the first finished and undo its edits. Coverage
+    // jobs count too, which keeps one replay per trace.
+    pub fn start(&mut self, trace_id: TraceID, max_frame_time: usize) -> Result<Arc<Job>, ApiError> {
+        self.check_idle(trace_id)?;
+        let job = Arc::new(Job {
+            id: self.next_id,
+            trace_id,
+            max_frame_time,
+            progress: JobProgress::default(),
+            outcome: Mutex::new((JobState::Running, None)),
+        });
+        self.next_id += 1;
+        self.jobs.insert(job.id, job.clone());
+        Ok(job)
+    }
+    pub fn get(&self, job_id: JobID) -> Result<Arc<Job>, ApiError> {
+        self.jobs
+            .get(&job_id)
+            .cloned()
+            .ok_or_else(|| ApiError::new(ApiErrorKind::NotFound, format!("No job with id {}", job_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let mut jobs = Jobs::default();
+        let job = jobs.start(0, 200).unwrap();
+        job.progress.set_frame_time(50);
+        let status = job.status();
+        assert_eq!((status.state, status.phase, status.percent), (JobState::Running, JobPhase::Replay, 25.0));
+        job.progress.set_phase(JobPhase::Synoptic);
+        assert_eq!(job.status().percent, 100.0);
+
+        assert_eq!(jobs.start(0, 200).unwrap_err().kind, ApiErrorKind::Conflict);
+        let other = jobs.start(1, 200).unwrap();
+        assert_ne!(other.id, job.id);
+
+        job.finish(Err(ApiError::new(ApiErrorKind::Internal, "replay failed")));
+        let status = jobs.get(job.id).unwrap().status();
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!(status.error.unwrap().message, "replay failed");
+        assert!(jobs.start(0, 200).is_ok());
+        assert_eq!(jobs.get(99).unwrap_err().kind, ApiErrorKind::NotFound);
+    }
+    #[test]
+    fn cancel() {
+        let mut jobs = Jobs::default();
+        let job = jobs.start(0, 10).unwrap();
+        assert!(job.progress.check_cancelled().is_ok());
+        job.progress.cancel();
+        let err = job.progress.check_cancelled().unwrap_err();
+        job.finish(Err(ApiError::new(ApiErrorKind::Internal, err.to_string())));
+        let status = job.status();
+        assert_eq!(status.state, JobState::Cancelled);
+        assert!(status.error.is_none());
+    }
+}
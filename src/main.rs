@@ -9,27 +9,46 @@ use clap::{Parser, Subcommand};
 use librr_rs::*;
 use shared_structs::*;
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
 use std::sync::Arc;
 
+use crate::config::SubstitutePath;
+use crate::file_parsing::FunctionPattern;
+use crate::events::EventBus;
+use crate::jobs::{Job, Jobs};
 use crate::simulation::Simulation;
 
 use actix_cors::Cors;
 use actix_web::{
-     middleware, web, App, HttpResponse, HttpServer,
+     dev::Service, http::header, http::StatusCode, middleware, web, App, HttpResponse, HttpServer,
+     ResponseError,
 };
 
 mod address_recorder;
+mod annotation_writer;
+mod auth;
 mod block;
+mod capture;
+mod condition;
+mod config;
+mod coverage;
 mod file_parsing;
 mod graph_builder;
+mod pack;
 mod erebor;
+mod events;
+mod export;
 mod gdb_instance_manager;
+mod jobs;
 mod recorder;
+mod session;
 mod shared_structs;
 mod simulation;
+mod spans;
+mod synoptic;
 mod trampoline;
+mod trampoline_replay;
 
 #[derive(Parser)]
 #[command(author,version,about,long_about = None)]
@@ -57,11 +76,29 @@ enum Commands {
             value_name = "SHOULD RECORD WITH FFMPEG"
         )]
         record_screen: bool,
+
+        /// Environment variable to add to the program's environment (repeatable)
+        #[arg(long = "env", value_name = "KEY=VAL", value_parser = parse_key_val)]
+        env: Vec<(String, String)>,
+
+        /// Working directory to start the program in
+        #[arg(long, value_name = "FOLDER")]
+        cwd: Option<PathBuf>,
+
+        /// File to feed to the program's stdin
+        #[arg(long, value_name = "FILE")]
+        stdin: Option<PathBuf>,
+
+        /// Arguments to pass to the program (after --)
+        #[arg(last = true, value_name = "ARGS")]
+        args: Vec<String>,
     },
-    /// Examine a recorded trace
+    /// Examine one or more recorded traces
     Explore {
-        /// Path to the save-dir of the last progrm
-        trace: PathBuf,
+        /// Paths to the save-dirs of recordings of the same program,
+        /// or bundles made by `pack`
+        #[arg(required = true)]
+        traces: Vec<PathBuf>,
         /// By default, this uses the procmap to fix the address offsets.
         /// Enabiling this option disables that. If you are writing your own asm
         /// or compiling glibc, you will want to enable this.
@@ -71,6 +108,100 @@ enum Commands {
             value_name = "USE PROCMAP TO FIX ADDR OFFSETS"
         )]
         no_glibc_offsets: bool,
+        /// Add entry and exit events to every function whose name
+        /// matches the glob, like a `functions` annotation
+        #[arg(long = "functions", value_name = "GLOB")]
+        functions: Vec<String>,
+        /// Restore a session saved from the UI into the recording
+        #[arg(long, value_name = "NAME")]
+        session: Option<String>,
+        /// Read source files under FROM from TO instead, like gdb's
+        /// `set substitute-path` (repeatable)
+        #[arg(long = "substitute-path", value_name = "FROM=TO", value_parser = SubstitutePath::parse)]
+        substitute_paths: Vec<SubstitutePath>,
+        /// Address to serve the UI on. 0.0.0.0 lets anyone who can
+        /// reach the port start gdb servers, combine it with --auth.
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Port to serve the UI on, 0 picks a free one
+        #[arg(long, default_value = "12000")]
+        port: u16,
+        /// Print the URL of the UI instead of opening a browser
+        #[arg(long)]
+        no_browser: bool,
+        /// Require a random token, which is part of the printed URL,
+        /// on every request. Use this on shared machines.
+        #[arg(long)]
+        auth: bool,
+    },
+    /// Render the graph of a recorded trace to a file without starting the server
+    Export {
+        /// Path to the save-dir of the recording or a bundle
+        trace: PathBuf,
+        /// Output format
+        #[arg(short, long, value_enum, default_value = "dot")]
+        format: export::ExportFormat,
+        /// File to write the graph to
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+        /// See `explore --no-glibc-offsets`
+        #[arg(
+            long,
+            default_value = "false",
+            value_name = "USE PROCMAP TO FIX ADDR OFFSETS"
+        )]
+        no_glibc_offsets: bool,
+        /// Infer the graph from one interleaved sequence of every
+        /// thread instead of one sequence per thread
+        #[arg(long)]
+        merge_threads: bool,
+        /// See `explore --functions`
+        #[arg(long = "functions", value_name = "GLOB")]
+        functions: Vec<String>,
+        /// See `explore --substitute-path`
+        #[arg(long = "substitute-path", value_name = "FROM=TO", value_parser = SubstitutePath::parse)]
+        substitute_paths: Vec<SubstitutePath>,
+    },
+    /// Bundle a recording with its binary, libraries and sources
+    /// so that it can be explored on another machine
+    Pack {
+        /// Path to the save-dir of the recording
+        trace: PathBuf,
+        /// Bundle to write, explore opens it directly
+        #[arg(short, long, value_name = "FILE.tar.zst")]
+        output: PathBuf,
+    },
+    /// Measure which source lines a recorded trace ran, by replaying
+    /// it with every long enough jump, call and return instrumented
+    Coverage {
+        /// Path to the save-dir of the recording or a bundle
+        trace: PathBuf,
+        /// File to write the coverage to, in the lcov format
+        #[arg(long, value_name = "FILE")]
+        lcov: PathBuf,
+        /// See `explore --no-glibc-offsets`
+        #[arg(
+            long,
+            default_value = "false",
+            value_name = "USE PROCMAP TO FIX ADDR OFFSETS"
+        )]
+        no_glibc_offsets: bool,
+        /// See `explore --substitute-path`
+        #[arg(long = "substitute-path", value_name = "FROM=TO", value_parser = SubstitutePath::parse)]
+        substitute_paths: Vec<SubstitutePath>,
+    },
+    /// Write the nodes and modules edited in a running `explore`
+    /// back into the annotations in the source files
+    WriteAnnotations {
+        /// Address of the explore server
+        #[arg(long, default_value = "127.0.0.1:12000")]
+        server: String,
+        /// Which of the explored traces to write the annotations of
+        #[arg(long, default_value = "0")]
+        trace_id: TraceID,
+        /// Token of a server started with --auth
+        #[arg(long)]
+        token: Option<String>,
     },
 }
 
@@ -82,9 +213,48 @@ enum Commands {
 struct SimulationStorage {
     traces: Vec<Simulation>,
     settings: Mutex<Settings>,
+    jobs: Mutex<Jobs>,
+    events: EventBus,
     //dwarf_data: Mutex<Erebor>,
     //graph_builder: Mutex<GraphBuilder>,
 }
+impl SimulationStorage {
+    fn get_trace(&self, trace_id: TraceID) -> Result<&Simulation, ApiError> {
+        self.traces.get(trace_id).ok_or_else(|| {
+            ApiError::new(ApiErrorKind::NotFound, format!("No trace with id {}", trace_id))
+        })
+    }
+}
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self.kind {
+            ApiErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ApiErrorKind::Forbidden => StatusCode::FORBIDDEN,
+            ApiErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiErrorKind::Annotation => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiErrorKind::Conflict => StatusCode::CONFLICT,
+            ApiErrorKind::BadRequest => StatusCode::BAD_REQUEST,
+            ApiErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<ApiError>() {
+            Ok(err) => err,
+            Err(err) => ApiError::new(ApiErrorKind::Internal, format!("{:#}", err)),
+        }
+    }
+}
+// A poisoned lock is reported instead of taking the worker down too
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>, ApiError> {
+    mutex
+        .lock()
+        .map_err(|err| ApiError::new(ApiErrorKind::Internal, err.to_string()))
+}
 async fn ping(req: web::Json<PingRequest>) -> HttpResponse {
     let req = req.0;
     HttpResponse::Ok().json(PingResponse { id: req.id })
@@ -92,24 +262,20 @@ async fn ping(req: web::Json<PingRequest>) -> HttpResponse {
 async fn get_instruction_pointer(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<InstructionPointerRequest>,
-) -> HttpResponse {
-    let ip = data.get_ref().traces[req.trace_id].last_rip.lock();
-    match ip {
-        Ok(instruction_pointer) => HttpResponse::Ok().json(InstructionPointerResponse {
-            instruction_pointer: *instruction_pointer,
-        }),
-        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-    }
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let instruction_pointer = lock(&simulation.last_rip)?;
+    Ok(HttpResponse::Ok().json(InstructionPointerResponse {
+        instruction_pointer: *instruction_pointer,
+    }))
 }
 async fn get_recorded_frames(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<RecordedFramesRequest>,
-) -> HttpResponse {
-    let mut frame_time_map = match data.get_ref().traces[req.trace_id].frame_time_map.lock() {
-        Ok(k) => k,
-        Err(k) => return HttpResponse::InternalServerError().body(k.to_string()),
-    };
-    let save_dir = data.as_ref().traces[req.trace_id].save_directory.clone();
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let mut frame_time_map = lock(&simulation.frame_time_map)?;
+    let save_dir = simulation.save_directory.clone();
     let to_load: Vec<String> = frame_time_map
         .frames
         .iter()
@@ -122,170 +288,491 @@ async fn get_recorded_frames(
         let dir = save_dir.join(frame_name.clone());
         response.frames.insert(frame_name, Vec::new());
     }
-    HttpResponse::Ok().json(response)
+    Ok(HttpResponse::Ok().json(response))
 }
 async fn get_general_info(
     data: web::Data<Arc<SimulationStorage>>,
     _req: web::Json<EmptyRequest>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let mut traces: Vec<TraceGeneralInfo> = Vec::new();
     let mut binary_name: Option<String> = None;
     let mut recording_dir: Option<PathBuf> = None;
     for (id, simulation) in data.as_ref().traces.iter().enumerate() {
-        let mut binary_interface = match simulation.bin_interface.lock() {
-            Ok(k) => k,
-            Err(k) => return HttpResponse::InternalServerError().body(k.to_string()),
-        };
+        let mut binary_interface = lock(&simulation.bin_interface)?;
         recording_dir = Some(simulation.save_directory.clone());
         if binary_name.is_none() {
             binary_name = Some(binary_interface.get_exec_file().into());
-        } // TODO ensure binary name is the same across all traces
-        let mut frame_time_map = match simulation.frame_time_map.lock() {
-            Ok(k) => k,
-            Err(k) => return HttpResponse::InternalServerError().body(k.to_string()),
-        };
+        } // run_server ensures the binary is the same across all traces
+        let mut frame_time_map = lock(&simulation.frame_time_map)?;
+        let proc_maps = binary_interface
+            .get_proc_map()
+            .map_err(|err| ApiError::new(ApiErrorKind::Internal, err.to_string()))?;
         traces.push(TraceGeneralInfo {
             id,
             frame_time_map: frame_time_map.clone(),
-            proc_maps: binary_interface.get_proc_map().unwrap().to_vec(),
+            proc_maps: proc_maps.to_vec(),
+            launch_info: simulation.launch_info.clone(),
         });
     }
+    // run_server refuses to start without a trace
+    let (Some(binary_name), Some(recording_dir)) = (binary_name, recording_dir) else {
+        return Err(ApiError::new(ApiErrorKind::NotFound, "No traces are loaded"));
+    };
     let data = GeneralInfoResponse {
-        binary_name: binary_name.unwrap(),
-        recording_dir: recording_dir.unwrap(),
+        binary_name,
+        recording_dir,
         traces,
     };
-    HttpResponse::Ok().json(data)
+    Ok(HttpResponse::Ok().json(data))
 }
 async fn get_current_graph(
     data: web::Data<Arc<SimulationStorage>>,
-    packet_version: web::Data<Arc<Mutex<usize>>>,
-    _req: web::Json<CurrentGraphRequest>,
-) -> HttpResponse {
-    // let mut packet_version = packet_version.get_ref().lock().unwrap();
-    // *packet_version+=1;
-
-    let dwarf_data = data.get_ref().traces[0].dwarf_data.lock().unwrap();
-    let mut graph_builder = data.get_ref().traces[0].graph_builder.lock().unwrap();
-    let settings = data.get_ref().settings.lock().unwrap();
-    let dot_data = graph_builder.get_graph_as_dot(&dwarf_data,&settings).unwrap();
+    req: web::Json<CurrentGraphRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let settings = lock(&data.get_ref().settings)?.clone();
+    let dwarf_data = lock(&simulation.dwarf_data)?;
+    let mut graph_builder = lock(&simulation.graph_builder)?;
+    let dot_data = graph_builder.get_graph_as_dot(&dwarf_data, &settings)?;
     // println!("{}",&dot_data.clone().unwrap());
     // dbg!(&data.get_ref().traces.len());
+    let Some(dot) = dot_data else {
+        return Err(ApiError::new(ApiErrorKind::Internal, "The graph has not been prepared"));
+    };
     let response: CurrentGraphResponse = CurrentGraphResponse {
-        version: 0,
-        dot: dot_data.unwrap(),
+        version: graph_builder.version,
+        dot,
     };
-    HttpResponse::Ok().json(response)
+    Ok(HttpResponse::Ok().json(response))
 }
 async fn create_gdb_server(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<CreateGdbServerRequest>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let req = req.0;
-    let simulation = &data.get_ref().traces[0];
-    let mut gdb_instance_manager = data.get_ref().traces[0].gdb_instance_mgr.lock().unwrap();
-    let to_ret = gdb_instance_manager.create_instance(&req.start_time, simulation);
-    // TODO
-    let response = CreateGdbServerResponse {
-        value: to_ret.unwrap_or_else(|m| format!("ERROR CREATING SERVER. {m}")),
-    };
-    HttpResponse::Ok().json(response)
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let mut gdb_instance_manager = lock(&simulation.gdb_instance_mgr)?;
+    let value = gdb_instance_manager
+        .create_instance(&req.start_time, simulation)
+        .map_err(|err| anyhow::anyhow!("Unable to create a gdb server: {:#}", err))?;
+    Ok(HttpResponse::Ok().json(CreateGdbServerResponse { value }))
+}
+// Replays the range with trampolines in a job. Its result is fetched
+// from /function_time_range_result once /events says it is done.
+async fn start_function_time_range(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<FunctionTimeRangeRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let req = req.0;
+    let trace_id = req.trace_id;
+    let simulation = data.get_ref().get_trace(trace_id)?;
+    let max_frame_time = lock(&simulation.graph_builder)?.max_frame_time();
+    let job = lock(&data.get_ref().jobs)?.start(trace_id, max_frame_time)?;
+    let job_id = job.id;
+    spawn_job(data.get_ref().clone(), job, move |storage, job| {
+        let simulation = storage.get_trace(trace_id)?;
+        let executed = trampoline_replay::record_executed(
+            &simulation.save_directory,
+            Some(&req.range),
+            &job.progress,
+        )?;
+        let addr_of_called_functions = trampoline_replay::called_functions(
+            &lock(&simulation.dwarf_data)?,
+            &executed.addresses,
+        );
+        *lock(&simulation.function_time_range)? = Some((
+            job.id,
+            FunctionTimeRangeResponse {
+                addr_of_called_functions,
+            },
+        ));
+        Ok(())
+    });
+    Ok(HttpResponse::Ok().json(StartFunctionTimeRangeResponse { job_id }))
+}
+async fn get_function_time_range_result(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<JobResultRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    match &*lock(&simulation.function_time_range)? {
+        Some((job_id, response)) if *job_id == req.job_id => Ok(HttpResponse::Ok().json(response)),
+        _ => Err(no_job_result(req.job_id)),
+    }
+}
+// Only the last result of a trace is kept
+fn no_job_result(job_id: JobID) -> ApiError {
+    ApiError::new(
+        ApiErrorKind::NotFound,
+        format!(
+            "No result for job {}, it is running, failed or was replaced",
+            job_id
+        ),
+    )
+}
+// Like /function_time_range, fetched from /function_heat_map_result
+async fn start_function_heat_map(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<FunctionExecutionHeatMapRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let req = req.0;
+    let trace_id = req.trace_id;
+    let simulation = data.get_ref().get_trace(trace_id)?;
+    let function = lock(&simulation.dwarf_data)?
+        .function_at(req.function_address)
+        .cloned()
+        .ok_or_else(|| {
+            ApiError::new(
+                ApiErrorKind::NotFound,
+                format!("No function at {:#x}", req.function_address),
+            )
+        })?;
+    let max_frame_time = lock(&simulation.graph_builder)?.max_frame_time();
+    let job = lock(&data.get_ref().jobs)?.start(trace_id, max_frame_time)?;
+    let job_id = job.id;
+    spawn_job(data.get_ref().clone(), job, move |storage, job| {
+        let simulation = storage.get_trace(trace_id)?;
+        let executed = trampoline_replay::record_executed(
+            &simulation.save_directory,
+            Some(&req.range),
+            &job.progress,
+        )?;
+        let map = trampoline_replay::heat_map(&lock(&simulation.dwarf_data)?, &function, &executed);
+        *lock(&simulation.heat_map)? = Some((job.id, FrameExecutionHeatMapResponse { map }));
+        Ok(())
+    });
+    Ok(HttpResponse::Ok().json(StartFunctionHeatMapResponse { job_id }))
+}
+async fn get_function_heat_map_result(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<JobResultRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    match &*lock(&simulation.heat_map)? {
+        Some((job_id, response)) if *job_id == req.job_id => Ok(HttpResponse::Ok().json(response)),
+        _ => Err(no_job_result(req.job_id)),
+    }
 }
 async fn get_addr_occurrences(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<AddrOccurrencesRequest>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let req = req.0;
-    let graph_builder = data.get_ref().traces[0].graph_builder.lock().unwrap();
-    let occurs = graph_builder.get_addr_occurrences(req.synoptic_node_id);
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let graph_builder = lock(&simulation.graph_builder)?;
+    let (val, captures) = graph_builder
+        .get_addr_occurrences(req.synoptic_node_id)?
+        .into_iter()
+        .unzip();
     //TODO
-    let response = AddrOccurrenceResponse { val: occurs };
-    HttpResponse::Ok().json(response)
+    let response = AddrOccurrenceResponse { val, captures };
+    Ok(HttpResponse::Ok().json(response))
+}
+async fn get_thread_lanes(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<ThreadLanesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let graph_builder = lock(&simulation.graph_builder)?;
+    let lanes = graph_builder
+        .get_thread_lanes()
+        .into_iter()
+        .map(|(tid, events)| ThreadLane { tid, events })
+        .collect();
+    Ok(HttpResponse::Ok().json(ThreadLanesResponse { lanes }))
+}
+async fn get_spans(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<SpansRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let frame_time_map = lock(&simulation.frame_time_map)?;
+    let graph_builder = lock(&simulation.graph_builder)?;
+    let spans = graph_builder.get_spans(&frame_time_map.times);
+    Ok(HttpResponse::Ok().json(SpansResponse { spans }))
 }
 async fn get_node_data(
     data: web::Data<Arc<SimulationStorage>>,
-    _req: web::Json<NodeDataRequest>,
-) -> HttpResponse {
-    let graph_builder = data.get_ref().traces[0].graph_builder.lock().unwrap();
+    req: web::Json<NodeDataRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let graph_builder = lock(&simulation.graph_builder)?;
     let resp = NodeDataResponse {
         modules: graph_builder.modules.clone(),
         nodes: graph_builder.synoptic_nodes.clone(),
     };
-    HttpResponse::Ok().json(resp)
+    Ok(HttpResponse::Ok().json(resp))
 }
 async fn get_raw_nodes_and_modules(
     data: web::Data<Arc<SimulationStorage>>,
-    _req: web::Json<GetRawNodesAndModulesRequest>,
-) -> HttpResponse {
-    let graph_builder = data.get_ref().traces[0].graph_builder.lock().unwrap();
+    req: web::Json<GetRawNodesAndModulesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let graph_builder = lock(&simulation.graph_builder)?;
     let resp = GetRawNodesAndModulesResponse {
         modules: graph_builder.modules.clone(),
         nodes: graph_builder.nodes.clone(),
     };
-    HttpResponse::Ok().json(resp)
+    Ok(HttpResponse::Ok().json(resp))
 }
+// Starts a job that prepares the graph from the new nodes and
+// modules. The previous graph is served until it is done.
 async fn update_raw_nodes_and_modules(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<UpdateRawNodesAndModulesRequest>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let req = req.0;
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let max_frame_time = lock(&simulation.graph_builder)?.max_frame_time();
+    let job = lock(&data.get_ref().jobs)?.start(req.trace_id, max_frame_time)?;
+    let job_id = job.id;
+    spawn_job(data.get_ref().clone(), job, move |storage, job| {
+        prepare_job(storage, job, req)
+    });
+    Ok(HttpResponse::Ok().json(UpdateRawNodesAndModulesResponse { job_id }))
+}
+// Runs work in a thread of its own and reports how the job went
+fn spawn_job(
+    storage: Arc<SimulationStorage>,
+    job: Arc<Job>,
+    work: impl FnOnce(&SimulationStorage, &Job) -> Result<(), ApiError> + Send + 'static,
+) {
+    watch_job(storage.clone(), job.clone());
+    std::thread::spawn(move || {
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| work(&storage, &job)))
+                .unwrap_or_else(|_| Err(ApiError::new(ApiErrorKind::Internal, "The job panicked")));
+        if let Err(err) = &result {
+            log::warn!("Job {} stopped: {}", job.id, err);
+        }
+        job.finish(result);
+        storage.events.send(ServerEvent::Job(job.status()));
+    });
+}
+// Prepares a copy of the graph builder so no lock is held during
+// the replay, then swaps it in
+fn prepare_job(
+    storage: &SimulationStorage,
+    job: &Job,
+    req: UpdateRawNodesAndModulesRequest,
+) -> Result<(), ApiError> {
+    let simulation = storage.get_trace(req.trace_id)?;
+    let mut settings = lock(&storage.settings)?.clone();
+    let erebor = lock(&simulation.dwarf_data)?.clone();
+    let mut graph_builder = lock(&simulation.graph_builder)?.clone();
 
-    let mut bin_interface =
-        BinaryInterface::new_at_target_event(0, data.get_ref().traces[0].save_directory.clone());
-    let cthread = bin_interface.get_current_thread();
-    bin_interface.pin_mut().set_query_thread(cthread);
-    bin_interface.set_pass_signals(vec![
-        0, 0xe, 0x14, 0x17, 0x1a, 0x1b, 0x1c, 0x21, 0x24, 0x25, 0x2c, 0x4c, 0x97,
-    ]);
-    let settings: &mut Settings = &mut data.get_ref().settings.lock().unwrap();
-    let erebor = data.get_ref().traces[0].dwarf_data.lock().unwrap();
-    let mut graph_builder = data.get_ref().traces[0].graph_builder.lock().unwrap();
+    graph_builder.update_raw_modules(req.modules)?;
+    graph_builder.update_raw_nodes(req.nodes, &erebor)?;
+    let mut bin_interface = simulation.reset_the_bin_interface()?;
+    graph_builder.prepare(&mut bin_interface, &erebor, req.rerun_level, &job.progress)?;
+    job.progress.check_cancelled()?;
+    job.progress.set_phase(JobPhase::Layout);
     settings.selected_node_id = None;
-    graph_builder.update_raw_modules(req.modules).unwrap();
-    graph_builder.update_raw_nodes(req.nodes, &erebor).unwrap();
-    graph_builder.prepare(&mut bin_interface, req.rerun_level);
+    graph_builder.get_graph_as_dot(&erebor, &settings)?;
+    job.progress.check_cancelled()?;
 
-    let resp = UpdateRawNodesAndModulesResponse {};
-    HttpResponse::Ok().json(resp)
+    let mut settings = lock(&storage.settings)?;
+    settings.selected_node_id = None;
+    settings.version += 1;
+    let version = graph_builder.version;
+    *lock(&simulation.graph_builder)? = graph_builder;
+    storage.events.send(ServerEvent::Settings {
+        version: settings.version,
+    });
+    storage.events.send(ServerEvent::Graph {
+        trace_id: req.trace_id,
+        version,
+    });
+    Ok(())
+}
+// Starts a job that replays the whole trace to measure which lines
+// it ran. /events tells when the result can be read from /coverage.
+async fn start_coverage(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<StartCoverageRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let trace_id = req.trace_id;
+    let simulation = data.get_ref().get_trace(trace_id)?;
+    let max_frame_time = lock(&simulation.graph_builder)?.max_frame_time();
+    let job = lock(&data.get_ref().jobs)?.start(trace_id, max_frame_time)?;
+    let job_id = job.id;
+    spawn_job(data.get_ref().clone(), job, move |storage, job| {
+        let simulation = storage.get_trace(trace_id)?;
+        let measured = coverage::measure(simulation, &job.progress)?;
+        *lock(&simulation.coverage)? = Some(measured);
+        storage.events.send(ServerEvent::Coverage { trace_id });
+        Ok(())
+    });
+    Ok(HttpResponse::Ok().json(StartCoverageResponse { job_id }))
+}
+async fn get_coverage(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<CoverageRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let coverage = lock(&simulation.coverage)?.clone();
+    Ok(HttpResponse::Ok().json(CoverageResponse { coverage }))
+}
+// Sends the progress of a running job to /events every time it
+// changes, at most a few times a second
+fn watch_job(storage: Arc<SimulationStorage>, job: Arc<Job>) {
+    std::thread::spawn(move || {
+        let mut last_status = None;
+        while job.is_running() {
+            let status = job.status();
+            if last_status.as_ref() != Some(&status) {
+                storage.events.send(ServerEvent::Job(status.clone()));
+                last_status = Some(status);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(250));
+        }
+    });
+}
+async fn get_job_status(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<JobStatusRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let job = lock(&data.get_ref().jobs)?.get(req.job_id)?;
+    Ok(HttpResponse::Ok().json(job.status()))
+}
+// The job stops at its next step and the previous graph is kept
+async fn cancel_job(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<CancelJobRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let job = lock(&data.get_ref().jobs)?.get(req.job_id)?;
+    job.progress.cancel();
+    Ok(HttpResponse::Ok().json(job.status()))
+}
+async fn write_annotations(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<WriteAnnotationsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let mut graph_builder = lock(&simulation.graph_builder)?;
+    let resp = annotation_writer::write_annotations(&mut graph_builder);
+    Ok(HttpResponse::Ok().json(resp))
+}
+async fn save_session(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<SaveSessionRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let settings = lock(&data.get_ref().settings)?;
+    let graph_builder = lock(&simulation.graph_builder)?;
+    let session = session::Session::new(&settings, &graph_builder);
+    let path = session::save(&simulation.save_directory, &req.name, &session)?;
+    Ok(HttpResponse::Ok().json(SaveSessionResponse { path }))
+}
+async fn load_session(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<LoadSessionRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    lock(&data.get_ref().jobs)?.check_idle(req.trace_id)?;
+    let loaded = session::load(&simulation.save_directory, &req.name)?;
+    let settings: &mut Settings = &mut lock(&data.get_ref().settings)?;
+    session::restore(simulation, settings, loaded)?;
+    let events = &data.get_ref().events;
+    events.send(ServerEvent::Settings {
+        version: settings.version,
+    });
+    events.send(ServerEvent::Graph {
+        trace_id: req.trace_id,
+        version: lock(&simulation.graph_builder)?.version,
+    });
+    Ok(HttpResponse::Ok().json(LoadSessionResponse {
+        settings: settings.clone(),
+    }))
+}
+async fn list_sessions(
+    data: web::Data<Arc<SimulationStorage>>,
+    req: web::Json<ListSessionsRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let names = session::list(&simulation.save_directory);
+    Ok(HttpResponse::Ok().json(ListSessionsResponse { names }))
 }
 async fn get_all_source_files(
     data: web::Data<Arc<SimulationStorage>>,
-    _req: web::Json<NodeDataRequest>,
-) -> HttpResponse {
-    let erebor = data.get_ref().traces[0].dwarf_data.lock().unwrap();
+    req: web::Json<AllSourceFilesRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    let erebor = lock(&simulation.dwarf_data)?;
     let out = erebor.files.keys().map(|k| (*k).clone()).collect();
 
     let resp = AllSourceFilesResponse { files: out };
-    HttpResponse::Ok().json(resp)
+    Ok(HttpResponse::Ok().json(resp))
 }
 async fn get_settings(
     data: web::Data<Arc<SimulationStorage>>,
     _req: web::Json<GetSettingsRequest>,
-) -> HttpResponse {
-    let settings: Settings = data.get_ref().settings.lock().unwrap().clone();
-    HttpResponse::Ok().json(settings)
+) -> Result<HttpResponse, ApiError> {
+    let settings: Settings = lock(&data.get_ref().settings)?.clone();
+    Ok(HttpResponse::Ok().json(settings))
 }
 async fn set_settings(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<SetSettingsRequest>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let req = req.0;
-    let settings: &mut Settings = &mut data.get_ref().settings.lock().unwrap();
+    let settings: &mut Settings = &mut lock(&data.get_ref().settings)?;
     *settings = req.settings;
     settings.version += 1;
-    HttpResponse::Ok().json(settings.clone())
+    data.get_ref().events.send(ServerEvent::Settings {
+        version: settings.version,
+    });
+    Ok(HttpResponse::Ok().json(settings.clone()))
+}
+// Server-sent events with the graph and settings versions and the
+// progress of jobs, so every client sees what the others changed
+async fn get_events(data: web::Data<Arc<SimulationStorage>>) -> Result<HttpResponse, ApiError> {
+    let storage = data.get_ref();
+    let mut current = vec![ServerEvent::Settings {
+        version: lock(&storage.settings)?.version,
+    }];
+    for (trace_id, simulation) in storage.traces.iter().enumerate() {
+        current.push(ServerEvent::Graph {
+            trace_id,
+            version: lock(&simulation.graph_builder)?.version,
+        });
+    }
+    current.extend(lock(&storage.jobs)?.running().map(|job| ServerEvent::Job(job.status())));
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(storage.events.stream(current)))
 }
 async fn get_source_file(
     data: web::Data<Arc<SimulationStorage>>,
     req: web::Json<SourceFileRequest>,
-) -> HttpResponse {
+) -> Result<HttpResponse, ApiError> {
     let req = req.0;
-    // TODO Checks here
-    let contents = std::fs::read_to_string(req.file_name);
-    let resp = SourceFileResponse {
-        data: contents.unwrap_or("[empty]".into()),
+    let simulation = data.get_ref().get_trace(req.trace_id)?;
+    // Only files from the DWARF data are served, anything else on
+    // the host is off limits
+    let file_name = lock(&simulation.dwarf_data)?.source_file(Path::new(&req.file_name));
+    let Some(file_name) = file_name else {
+        return Err(ApiError::new(
+            ApiErrorKind::Forbidden,
+            format!("{} is not a source file of trace {}", req.file_name, req.trace_id),
+        ));
     };
-    HttpResponse::Ok().json(resp)
+    let contents = std::fs::read_to_string(&file_name).map_err(|err| {
+        ApiError::new(
+            ApiErrorKind::NotFound,
+            format!("Unable to read {}: {}", file_name.to_string_lossy(), err),
+        )
+    })?;
+    Ok(HttpResponse::Ok().json(SourceFileResponse { data: contents }))
+}
+// Loaded by index.html before the app so it knows where the API is
+async fn get_frontend_config(config: web::Data<FrontendConfig>) -> Result<HttpResponse, ApiError> {
+    let json = serde_json::to_string(config.get_ref())
+        .map_err(|err| ApiError::new(ApiErrorKind::Internal, err.to_string()))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/javascript")
+        .body(format!("window.explorantConfig = {};", json)))
 }
 
 #[actix_web::main]
@@ -303,56 +790,272 @@ async fn main() -> std::io::Result<()> {
             exe,
             save_dir,
             record_screen,
+            env,
+            cwd,
+            stdin,
+            args,
         } => {
-            recorder::record(exe, save_dir, *record_screen, None);
-            Ok(())
+            let launch_info = LaunchInfo {
+                exe: exe.clone(),
+                args: args.clone(),
+                env: env.clone(),
+                cwd: cwd.clone(),
+                stdin: stdin.clone(),
+            };
+            recorder::record(&launch_info, save_dir, *record_screen)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err)))
         }
         Commands::Explore {
+            traces,
+            no_glibc_offsets,
+            functions,
+            session,
+            substitute_paths,
+            bind,
+            port,
+            no_browser,
+            auth,
+        } => {
+            let function_patterns = function_patterns(functions);
+            let serve_options = ServeOptions {
+                bind: bind.clone(),
+                port: *port,
+                open_browser: !*no_browser,
+                token: auth.then(auth::new_token),
+            };
+            return run_server(
+                traces.clone(),
+                !*no_glibc_offsets,
+                function_patterns,
+                session.clone(),
+                substitute_paths.clone(),
+                serve_options,
+            )
+            .await;
+        }
+        Commands::Export {
+            trace,
+            format,
+            output,
+            no_glibc_offsets,
+            merge_threads,
+            functions,
+            substitute_paths,
+        } => export::export(
+            trace,
+            *format,
+            output,
+            !*no_glibc_offsets,
+            !*merge_threads,
+            &function_patterns(functions),
+            substitute_paths,
+        )
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err))),
+        Commands::Pack { trace, output } => pack::pack(trace, output)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err))),
+        Commands::Coverage {
             trace,
+            lcov,
             no_glibc_offsets,
+            substitute_paths,
+        } => coverage::export_lcov(trace, lcov, !*no_glibc_offsets, substitute_paths)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err))),
+        Commands::WriteAnnotations {
+            server,
+            trace_id,
+            token,
         } => {
-            return run_server(vec![trace.clone()], !*no_glibc_offsets).await;
+            let resp = annotation_writer::request_write(server, *trace_id, token.as_deref())
+                .map_err(|err| {
+                    std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", err))
+                })?;
+            for file in &resp.written {
+                println!("wrote {}", file.to_string_lossy());
+            }
+            for conflict in &resp.conflicts {
+                println!("conflict {}: {}", conflict.file.to_string_lossy(), conflict.reason);
+            }
+            for skipped in &resp.skipped {
+                println!("skipped {}", skipped);
+            }
+            Ok(())
         }
     }
 }
+// --functions globs go in the root module
+fn function_patterns(globs: &[String]) -> Vec<FunctionPattern> {
+    globs
+        .iter()
+        .map(|glob| FunctionPattern {
+            pattern: Some(glob.clone()),
+            file: None,
+            module: "".into(),
+        })
+        .collect()
+}
+fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    let (key, val) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid KEY=VAL: no `=` found in `{}`", s))?;
+    Ok((key.to_string(), val.to_string()))
+}
 fn react_frontend_app() -> actix_web::Result<actix_files::NamedFile> {
     let path: PathBuf = PathBuf::from("./frontend/build/index.html");
     Ok(actix_files::NamedFile::open(path)?)
 }
-async fn run_server(traces: Vec<PathBuf>, offset_addrs_with_map: bool) -> std::io::Result<()> {
+// Where the UI is served and whether to open it
+struct ServeOptions {
+    bind: String,
+    // 0 picks a free port
+    port: u16,
+    open_browser: bool,
+    // Required on every request when set
+    token: Option<String>,
+}
+async fn run_server(
+    traces: Vec<PathBuf>,
+    offset_addrs_with_map: bool,
+    function_patterns: Vec<FunctionPattern>,
+    session_name: Option<String>,
+    substitute_paths: Vec<SubstitutePath>,
+    serve_options: ServeOptions,
+) -> std::io::Result<()> {
     if traces.len() == 0 {
         log::error!("You must pass at least one trace");
         // TODO: Anyhow this with proper msg
         return Ok(());
     }
-    let traces = traces
+    let traces: Vec<Simulation> = traces
         .iter()
-        .map(|t| Simulation::new(t.clone(), offset_addrs_with_map).unwrap())
-        .collect();
+        .map(|t| {
+            Simulation::new(
+                t.clone(),
+                offset_addrs_with_map,
+                &function_patterns,
+                &substitute_paths,
+            )
+            .map_err(|err| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Unable to open {}: {:?}", t.display(), err),
+                )
+            })
+        })
+        .collect::<std::io::Result<_>>()?;
+    // Comparing traces only makes sense if they share
+    // the same DWARF data and addresses
+    if let Some(mismatch) = traces.iter().find(|t| t.binary_id != traces[0].binary_id) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            format!(
+                "All traces must be recordings of the same binary ({} vs {})",
+                traces[0].save_directory.display(),
+                mismatch.save_directory.display()
+            ),
+        ));
+    }
+    let mut settings = Settings::default();
+    if let Some(name) = &session_name {
+        // A session is saved into one trace, restore it wherever it is
+        let mut restored = false;
+        for trace in &traces {
+            if !session::list(&trace.save_directory).contains(name) {
+                continue;
+            }
+            let loaded = session::load(&trace.save_directory, name)
+                .and_then(|loaded| session::restore(trace, &mut settings, loaded));
+            loaded.map_err(|err| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("Unable to restore session {}: {:?}", name, err),
+                )
+            })?;
+            restored = true;
+        }
+        if !restored {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No trace has a session named {}", name),
+            ));
+        }
+    }
     let simulation: Arc<SimulationStorage> = Arc::new(SimulationStorage {
         traces,
-        settings: Mutex::new(Settings::default()),
-    });
-    let packet_version: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
-    let port = 12000;
-    let ip = "0.0.0.0";
-    log::info!("Starting HTTP server at {}:{}", &ip, port);
-    std::thread::spawn(move || {
-        while port_scanner::local_port_available(12000) {
-            std::thread::sleep(std::time::Duration::from_millis(20));
-        }
-        std::process::Command::new("/usr/bin/xdg-open")
-            .arg(format!("http://localhost:{}", port))
-            .output()
-            .expect("failed to start web browser at port");
+        settings: Mutex::new(settings),
+        jobs: Mutex::new(Jobs::default()),
+        events: EventBus::default(),
     });
+    // Bound before the server is built so the frontend can be told
+    // which port was picked
+    let listener = std::net::TcpListener::bind((serve_options.bind.as_str(), serve_options.port))?;
+    let address = listener.local_addr()?;
+    let frontend_config = FrontendConfig {
+        port: address.port(),
+    };
+    let mut url = if address.ip().is_unspecified() {
+        format!("http://localhost:{}", address.port())
+    } else {
+        format!("http://{}", address)
+    };
+    let token = serve_options.token;
+    if let Some(token) = &token {
+        url = format!("{}/?token={}", url, token);
+    }
+    log::info!("Starting HTTP server at {}, open {}", address, url);
+    if serve_options.open_browser {
+        std::thread::spawn(move || {
+            if let Err(err) = std::process::Command::new("xdg-open").arg(&url).output() {
+                log::warn!("Unable to open a browser ({}), open {} instead", err, url);
+            }
+        });
+    }
     HttpServer::new(move || {
+        let token = token.clone();
+        // Browsers only send the cookie cross origin if credentials
+        // are allowed, so they are not when it is the only protection
+        let cors = if token.is_some() {
+            Cors::default()
+                .allow_any_origin()
+                .allow_any_method()
+                .allow_any_header()
+        } else {
+            Cors::permissive()
+        };
         App::new()
+            .wrap_fn(move |req, srv| {
+                let access = match &token {
+                    Some(token) => auth::check(
+                        token,
+                        req.headers()
+                            .get(header::AUTHORIZATION)
+                            .and_then(|value| value.to_str().ok()),
+                        req.cookie(auth::TOKEN_COOKIE).as_ref().map(|cookie| cookie.value()),
+                        req.query_string(),
+                    ),
+                    None => auth::Access::Granted,
+                };
+                let cookie = token.as_deref().map(auth::token_cookie);
+                let response = (access != auth::Access::Denied).then(|| srv.call(req));
+                async move {
+                    let Some(response) = response else {
+                        return Err(ApiError::new(
+                            ApiErrorKind::Unauthorized,
+                            "Missing or wrong token, open the URL printed by explorant",
+                        )
+                        .into());
+                    };
+                    let mut response = response.await?;
+                    if let (auth::Access::GrantedByQuery, Some(cookie)) = (access, cookie) {
+                        response.response_mut().add_cookie(&cookie)?;
+                    }
+                    Ok(response)
+                }
+            })
             // enable logger
             .wrap(middleware::Logger::default())
-            .wrap(Cors::permissive())
+            .wrap(cors)
             .app_data(web::Data::new(simulation.clone()))
-            .app_data(web::Data::new(packet_version.clone()))
+            .app_data(web::Data::new(frontend_config.clone()))
             .app_data(web::JsonConfig::default().limit(1073741824))
             .service(web::resource("/ping").route(web::post().to(ping)))
             .service(
@@ -367,7 +1070,21 @@ async fn run_server(traces: Vec<PathBuf>, offset_addrs_with_map: bool) -> std::i
             .service(web::resource("/set_settings").route(web::post().to(set_settings)))
             .service(web::resource("/get_settings").route(web::post().to(get_settings)))
             .service(web::resource("/create_gdb_server").route(web::post().to(create_gdb_server)))
+            .service(web::resource("/function_time_range").route(web::post().to(start_function_time_range)))
+            .service(
+                web::resource("/function_time_range_result")
+                    .route(web::post().to(get_function_time_range_result)),
+            )
+            .service(web::resource("/function_heat_map").route(web::post().to(start_function_heat_map)))
+            .service(
+                web::resource("/function_heat_map_result")
+                    .route(web::post().to(get_function_heat_map_result)),
+            )
+            .service(web::resource("/start_coverage").route(web::post().to(start_coverage)))
+            .service(web::resource("/coverage").route(web::post().to(get_coverage)))
             .service(web::resource("/addr_occurrences").route(web::post().to(get_addr_occurrences)))
+            .service(web::resource("/thread_lanes").route(web::post().to(get_thread_lanes)))
+            .service(web::resource("/spans").route(web::post().to(get_spans)))
             .service(web::resource("/source_files").route(web::post().to(get_all_source_files)))
             .service(
                 web::resource("/get_raw_nodes_and_modules")
@@ -377,11 +1094,19 @@ async fn run_server(traces: Vec<PathBuf>, offset_addrs_with_map: bool) -> std::i
                 web::resource("/update_raw_nodes_and_modules")
                     .route(web::post().to(update_raw_nodes_and_modules)),
             )
+            .service(web::resource("/events").route(web::get().to(get_events)))
+            .service(web::resource("/job_status").route(web::post().to(get_job_status)))
+            .service(web::resource("/cancel_job").route(web::post().to(cancel_job)))
+            .service(web::resource("/write_annotations").route(web::post().to(write_annotations)))
+            .service(web::resource("/save_session").route(web::post().to(save_session)))
+            .service(web::resource("/load_session").route(web::post().to(load_session)))
+            .service(web::resource("/sessions").route(web::post().to(list_sessions)))
+            .service(web::resource("/config.js").route(web::get().to(get_frontend_config)))
             // .route("/", web::get().to(react_frontend_app))
             .service(actix_files::Files::new("/", "./frontend/build").index_file("index.html"))
     })
     .workers(8)
-    .bind((ip, port))?
+    .listen(listener)?
     .run()
     .await
 }
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::erebor::Erebor;
+use crate::file_parsing::{self, SIDECAR_FILE_NAME};
+use crate::recorder::LAUNCH_INFO_FILE_NAME;
+use crate::shared_structs::LaunchInfo;
+use crate::simulation::Simulation;
+
+// A bundle is a recording that can be explored on another machine.
+// It is a zstd compressed tar of
+//   - the recording directory after `rr pack`, which copies the binary
+//     and its shared libraries into the trace. The frames, the event
+//     cache and the sessions are already in there.
+//   - the sidecar annotations, if they are next to the program instead
+//   - the binary and every source file in its DWARF data under
+//     packed_files/ at their original path
+//   - a manifest of those original paths so they can be remapped
+
+pub const BUNDLE_EXTENSION: &str = ".tar.zst";
+const MANIFEST_FILE_NAME: &str = "explorant_pack.json";
+const PACKED_FILES_DIR: &str = "packed_files";
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct PackManifest {
+    version: u32,
+    // As given by get_exec_file when packed
+    exec_file: PathBuf,
+    // Source files as named in the DWARF data
+    files: Vec<PathBuf>,
+}
+
+pub fn pack(trace: &Path, output: &Path) -> anyhow::Result<()> {
+    if trace.join(MANIFEST_FILE_NAME).exists() {
+        anyhow::bail!(
+            "{} was unpacked from a bundle, share the bundle instead",
+            trace.to_string_lossy()
+        );
+    }
+    let status = Command::new("rr")
+        .arg("pack")
+        .arg(trace)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Unable to run rr pack: {}", e))?;
+    if !status.success() {
+        anyhow::bail!("rr pack {} failed ({})", trace.to_string_lossy(), status);
+    }
+
+    let exec_file = PathBuf::from(Simulation::open_bin_interface(&trace.to_path_buf(), 0).get_exec_file());
+    let symbol_str = std::fs::read(&exec_file)?;
+    let obj_file = object::File::parse(&*symbol_str)?;
+    let erebor = Erebor::unrelocated(&obj_file);
+    let mut files: Vec<PathBuf> = erebor
+        .files
+        .keys()
+        .filter(|file| {
+            let exists = file.is_file();
+            if !exists {
+                log::warn!("Not packing missing source file {}", file.to_string_lossy());
+            }
+            exists
+        })
+        .cloned()
+        .collect();
+    files.sort();
+
+    let launch_info: Option<LaunchInfo> = match File::open(trace.join(LAUNCH_INFO_FILE_NAME)) {
+        Ok(file) => Some(serde_json::from_reader(std::io::BufReader::new(file))?),
+        Err(_) => None,
+    };
+    let sidecar = file_parsing::find_sidecar(trace, launch_info.as_ref());
+    let manifest = PackManifest {
+        version: MANIFEST_VERSION,
+        exec_file,
+        files,
+    };
+    write_bundle(trace, output, &manifest, sidecar.as_deref())?;
+    log::info!(
+        "Packed {} with {} source files into {}",
+        trace.to_string_lossy(),
+        manifest.files.len(),
+        output.to_string_lossy()
+    );
+    Ok(())
+}
+fn write_bundle(
+    trace: &Path,
+    output: &Path,
+    manifest: &PackManifest,
+    sidecar: Option<&Path>,
+) -> anyhow::Result<()> {
+    let encoder = zstd::Encoder::new(File::create(output)?, 0)?;
+    let mut builder = tar::Builder::new(encoder);
+    builder.append_dir_all(".", trace)?;
+    if let Some(sidecar) = sidecar.filter(|sidecar| !sidecar.starts_with(trace)) {
+        builder.append_path_with_name(sidecar, SIDECAR_FILE_NAME)?;
+    }
+    for file in std::iter::once(&manifest.exec_file).chain(&manifest.files) {
+        builder.append_path_with_name(file, packed_path(Path::new(""), file))?;
+    }
+    let manifest = serde_json::to_vec_pretty(manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_FILE_NAME, manifest.as_slice())?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+// The recording directory of a trace given on the command line.
+// A bundle is unpacked next to itself the first time it is opened.
+pub fn open_trace(trace: &Path) -> anyhow::Result<PathBuf> {
+    let name = trace
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(BUNDLE_EXTENSION));
+    let Some(name) = name.filter(|_| trace.is_file()) else {
+        return Ok(trace.to_path_buf());
+    };
+    let directory = trace.with_file_name(name);
+    if directory.exists() {
+        return Ok(directory);
+    }
+    log::info!(
+        "Unpacking {} into {}",
+        trace.to_string_lossy(),
+        directory.to_string_lossy()
+    );
+    // Unpack then rename so an interrupted unpack is not mistaken
+    // for a complete one next time
+    let tmp_directory = trace.with_file_name(format!("{}.unpacking", name));
+    if tmp_directory.exists() {
+        std::fs::remove_dir_all(&tmp_directory)?;
+    }
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(trace)?)?);
+    archive.unpack(&tmp_directory)?;
+    std::fs::rename(&tmp_directory, &directory)?;
+    Ok(directory)
+}
+
+// Maps the paths of the machine a bundle was packed on to the
+// files unpacked from it
+pub struct PathRemap {
+    root: PathBuf,
+    exec_file: PathBuf,
+    files: HashSet<PathBuf>,
+}
+impl PathRemap {
+    // None for recordings that did not come from a bundle
+    pub fn load(directory: &Path) -> anyhow::Result<Option<Self>> {
+        let file = match File::open(directory.join(MANIFEST_FILE_NAME)) {
+            Ok(file) => file,
+            Err(_) => return Ok(None),
+        };
+        let manifest: PackManifest = serde_json::from_reader(std::io::BufReader::new(file))?;
+        if manifest.version != MANIFEST_VERSION {
+            anyhow::bail!(
+                "{} was packed with version {} of the bundle format, expected {}",
+                directory.to_string_lossy(),
+                manifest.version,
+                MANIFEST_VERSION
+            );
+        }
+        Ok(Some(Self {
+            root: std::fs::canonicalize(directory)?,
+            exec_file: manifest.exec_file,
+            files: manifest.files.into_iter().collect(),
+        }))
+    }
+    pub fn remap(&self, path: &Path) -> Option<PathBuf> {
+        (path == self.exec_file || self.files.contains(path)).then(|| packed_path(&self.root, path))
+    }
+}
+// Absolute paths and paths with .. both end up inside packed_files
+fn packed_path(root: &Path, original: &Path) -> PathBuf {
+    let mut path = root.join(PACKED_FILES_DIR);
+    let mut depth = 0;
+    for component in original.components() {
+        match component {
+            Component::Normal(part) => {
+                path.push(part);
+                depth += 1;
+            }
+            Component::ParentDir if depth > 0 => {
+                path.pop();
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_paths() {
+        let root = Path::new("/bundle");
+        assert_eq!(
+            packed_path(root, Path::new("/home/ci/src/main.c")),
+            PathBuf::from("/bundle/packed_files/home/ci/src/main.c")
+        );
+        assert_eq!(
+            packed_path(root, Path::new("/build/src/../include/a.h")),
+            PathBuf::from("/bundle/packed_files/build/include/a.h")
+        );
+        assert_eq!(
+            packed_path(root, Path::new("../../etc/passwd")),
+            PathBuf::from("/bundle/packed_files/etc/passwd")
+        );
+    }
+    #[test]
+    fn bundle() {
+        let dir = std::env::temp_dir().join(format!("explorant-pack-{}", std::process::id()));
+        let trace = dir.join("recording");
+        std::fs::create_dir_all(trace.join("frames")).unwrap();
+        std::fs::write(trace.join("frame_time_map.json"), "{}").unwrap();
+        std::fs::write(trace.join("frames/out-000001.jpg"), "jpg").unwrap();
+        let source = dir.join("main.c");
+        std::fs::write(&source, "int main() {}\n").unwrap();
+        let exec_file = dir.join("a.out");
+        std::fs::write(&exec_file, "ELF").unwrap();
+        let sidecar = dir.join(SIDECAR_FILE_NAME);
+        std::fs::write(&sidecar, "[]").unwrap();
+
+        let manifest = PackManifest {
+            version: MANIFEST_VERSION,
+            exec_file: exec_file.clone(),
+            files: vec![source.clone()],
+        };
+        let bundle = dir.join("shared.tar.zst");
+        write_bundle(&trace, &bundle, &manifest, Some(&sidecar)).unwrap();
+
+        let unpacked = open_trace(&bundle).unwrap();
+        assert_eq!(unpacked, dir.join("shared"));
+        // Opened again without unpacking
+        assert_eq!(open_trace(&bundle).unwrap(), unpacked);
+        assert_eq!(open_trace(&trace).unwrap(), trace);
+        assert!(unpacked.join("frames/out-000001.jpg").is_file());
+        assert!(unpacked.join(SIDECAR_FILE_NAME).is_file());
+
+        let remap = PathRemap::load(&unpacked).unwrap().unwrap();
+        let local = remap.remap(&source).unwrap();
+        assert_eq!(std::fs::read_to_string(local).unwrap(), "int main() {}\n");
+        assert_eq!(std::fs::read_to_string(remap.remap(&exec_file).unwrap()).unwrap(), "ELF");
+        assert_eq!(remap.remap(Path::new("/usr/include/stdio.h")), None);
+        assert!(PathRemap::load(&trace).unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
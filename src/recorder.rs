@@ -1,5 +1,5 @@
 use nix::sys::signal::{self, Signal};
-use nix::unistd::Pid;
+use nix::unistd::{close, dup, dup2, Pid};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, File};
@@ -11,28 +11,82 @@ use std::{thread, u128};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
+use std::os::unix::io::{AsRawFd, RawFd};
 
 use librr_rs::RecordingInterface;
 
-use crate::shared_structs::FrameTimeMap;
+use crate::shared_structs::{FrameTimeMap, LaunchInfo};
 
 const RECORDING_TEMP_FILE_NAME: &str = "unique_temp_recording_output.mkv";
 const RECORDING_TEMP_TIMES_NAME: &str = "unique_temp_recording_output_times.txt";
+pub const LAUNCH_INFO_FILE_NAME: &str = "launch_info.json";
+
+// Puts our stdin and working directory back when dropped, so they are
+// restored even when the recording fails part way
+struct Redirection {
+    saved_stdin: Option<RawFd>,
+    original_cwd: PathBuf,
+}
+impl Drop for Redirection {
+    fn drop(&mut self) {
+        if let Err(e) = std::env::set_current_dir(&self.original_cwd) {
+            log::warn!("Unable to restore the working directory: {}", e);
+        }
+        if let Some(saved_stdin) = self.saved_stdin {
+            if let Err(e) = dup2(saved_stdin, 0).and_then(|_| close(saved_stdin)) {
+                log::warn!("Unable to restore stdin: {}", e);
+            }
+        }
+    }
+}
 
 pub fn record(
-    exe_path: &PathBuf,
+    launch_info: &LaunchInfo,
     output_directory: &PathBuf,
     record_screen: bool,
-    exe_args: Option<&str>,
 ) -> anyhow::Result<()> {
     remove_dir_all(output_directory);
+    // The tracee may be started in a different working directory
+    // so every path handed to rr has to be absolute.
+    let original_cwd = std::env::current_dir()?;
+    let output_directory = original_cwd.join(output_directory);
+    let launch_info = LaunchInfo {
+        exe: original_cwd.join(&launch_info.exe),
+        cwd: launch_info.cwd.as_ref().map(|cwd| original_cwd.join(cwd)),
+        stdin: launch_info.stdin.as_ref().map(|stdin| original_cwd.join(stdin)),
+        ..launch_info.clone()
+    };
     let output_directory_str = output_directory
         .to_str()
         .ok_or_else(|| anyhow::Error::msg("Output directory cannot be turned into a str"))?;
-    let exe_path_str = exe_path
+    let exe_path_str = launch_info
+        .exe
         .to_str()
         .ok_or_else(|| anyhow::Error::msg("Exe path cannot be turned into a str"))?;
 
+    let mut rr_args: Vec<String> = vec!["--output-trace-dir".into(), output_directory_str.into()];
+    for (key, value) in &launch_info.env {
+        rr_args.push(format!("--env={}={}", key, value));
+    }
+    rr_args.push(exe_path_str.into());
+    rr_args.extend(launch_info.args.iter().cloned());
+    // RecordingInterface takes a single command line string
+    // which is split on whitespace.
+    if let Some(arg) = rr_args.iter().find(|arg| arg.contains(char::is_whitespace)) {
+        anyhow::bail!("Arguments and environment values cannot contain whitespace ({:?})", arg);
+    }
+    let stdin_file = match &launch_info.stdin {
+        Some(stdin) => Some(File::open(stdin).map_err(|e| {
+            anyhow::anyhow!("Unable to open stdin file {}: {}", stdin.to_string_lossy(), e)
+        })?),
+        None => None,
+    };
+    if let Some(cwd) = &launch_info.cwd {
+        if !cwd.is_dir() {
+            anyhow::bail!("Working directory {} does not exist", cwd.to_string_lossy());
+        }
+    }
+
     // https://stackoverflow.com/questions/53391150/ffmpeg-obtain-the-system-time-corresponding-to-each-frame-present-in-a-video
     // TODO: The waiting in this is terrrible and
     // brittle. Refactor to use pipes from the child
@@ -79,12 +133,21 @@ pub fn record(
     if record_screen {
         thread::sleep(Duration::from_millis(5000));
     }
-    let mut rec_interface = RecordingInterface::new(format!(
-        "--output-trace-dir {} {} {}",
-        output_directory_str,
-        exe_path_str,
-        exe_args.unwrap_or("")
-    ));
+    // The tracee inherits our cwd and stdin so swap them
+    // out for the duration of the recording
+    let mut redirection = Redirection {
+        saved_stdin: None,
+        original_cwd: original_cwd.clone(),
+    };
+    if let Some(stdin_file) = &stdin_file {
+        let saved_stdin = dup(0)?;
+        redirection.saved_stdin = Some(saved_stdin);
+        dup2(stdin_file.as_raw_fd(), 0)?;
+    }
+    if let Some(cwd) = &launch_info.cwd {
+        std::env::set_current_dir(cwd)?;
+    }
+    let mut rec_interface = RecordingInterface::new(rr_args.join(" "));
     let mut frame_times_to_system_milis: HashMap<i64, u128> = HashMap::new();
 
     while rec_interface.pin_mut().continue_recording() {
@@ -99,6 +162,7 @@ pub fn record(
                 .as_millis(),
         );
     }
+    drop(redirection);
     if record_screen {
         thread::sleep(Duration::from_millis(1000));
         signal::kill(Pid::from_raw(child.unwrap().id() as i32), Signal::SIGINT)?;
@@ -185,6 +249,8 @@ pub fn record(
         "{}",
         serde_json::to_string(&frametimemap)?
     )?;
+    let mut launchinfofile = File::create(output_directory.join(LAUNCH_INFO_FILE_NAME))?;
+    write!(launchinfofile, "{}", serde_json::to_string(&launch_info)?)?;
 
     if record_screen {
         remove_file(RECORDING_TEMP_TIMES_NAME)?;
@@ -226,7 +292,7 @@ mod tests {
         let save_dir = std::env::temp_dir().join(format!("mqp_temp_{}", random_number.to_string()));
         let mut output = String::new();
         let mut stdout_buf = BufferRedirect::stdout().unwrap();
-        super::record(&exe_dir, &save_dir, true, None)?;
+        super::record(&LaunchInfo::new(exe_dir), &save_dir, true)?;
         stdout_buf.read_to_string(&mut output).unwrap();
         drop(stdout_buf);
         assert!(output.contains("Started"));
@@ -247,7 +313,11 @@ mod tests {
         let save_dir = std::env::temp_dir().join(format!("mqp_temp_{}", random_number.to_string()));
         let mut output = String::new();
         let mut stdout_buf = BufferRedirect::stdout().unwrap();
-        super::record(&exe_dir, &save_dir, true, Some("100"))?;
+        let launch_info = LaunchInfo {
+            args: vec!["100".into()],
+            ..LaunchInfo::new(exe_dir)
+        };
+        super::record(&launch_info, &save_dir, true)?;
         stdout_buf.read_to_string(&mut output).unwrap();
         drop(stdout_buf);
         assert!(output.contains("Started"));
@@ -262,6 +332,11 @@ mod tests {
         // this happens when there is not enough delay after
         // starting the recording. It makes the first avaiable frame equal to the last.
         //assert!(map.frames.last().unwrap().2 != "frames/out-000002.jpg");
+
+        let file = File::open(save_dir.join(LAUNCH_INFO_FILE_NAME))?;
+        let reader = std::io::BufReader::new(file);
+        let saved_launch_info: LaunchInfo = serde_json::from_reader(reader)?;
+        assert_eq!(saved_launch_info, launch_info);
         Ok(())
     }
     #[test]
@@ -277,7 +352,11 @@ mod tests {
         // log::error!("{:?}",&save_dir);
         let mut output = String::new();
         let mut stdout_buf = BufferRedirect::stdout().unwrap();
-        super::record(&exe_dir, &save_dir, true, Some("10000"))?;
+        let launch_info = LaunchInfo {
+            args: vec!["10000".into()],
+            ..LaunchInfo::new(exe_dir)
+        };
+        super::record(&launch_info, &save_dir, true)?;
         stdout_buf.read_to_string(&mut output).unwrap();
         drop(stdout_buf);
         assert!(output.contains("Started"));
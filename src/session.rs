@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph_builder::GraphBuilder;
+use crate::jobs::JobProgress;
+use crate::shared_structs::{GraphModule, GraphNode, Settings};
+use crate::simulation::Simulation;
+
+// Sessions are saved in the recording next to frame_time_map.json
+// as <name>.session.json
+const SESSION_SUFFIX: &str = ".session.json";
+const SESSION_VERSION: u32 = 1;
+
+// Everything needed to show someone else the graph as it was saved:
+// the settings (with the selected node), the modules with their
+// collapsed attributes and the nodes including edits not yet written
+// back to the source.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Session {
+    version: u32,
+    pub settings: Settings,
+    pub modules: HashMap<String, GraphModule>,
+    // Keyed by address like GraphBuilder.nodes
+    pub nodes: HashMap<usize, GraphNode>,
+}
+impl Session {
+    pub fn new(settings: &Settings, graph_builder: &GraphBuilder) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            settings: settings.clone(),
+            modules: graph_builder.modules.clone(),
+            nodes: graph_builder.nodes.clone(),
+        }
+    }
+}
+
+pub fn session_path(save_directory: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    // The name ends up in a path
+    let is_valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.is_empty() || !name.chars().all(is_valid) {
+        anyhow::bail!(
+            "Invalid session name `{}`. Use letters, digits, - and _",
+            name
+        );
+    }
+    Ok(save_directory.join(format!("{}{}", name, SESSION_SUFFIX)))
+}
+pub fn save(save_directory: &Path, name: &str, session: &Session) -> anyhow::Result<PathBuf> {
+    let path = session_path(save_directory, name)?;
+    // Write then rename like the event cache
+    let tmp_path = path.with_extension("json.tmp");
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+    serde_json::to_writer_pretty(&mut writer, session)?;
+    writer.flush()?;
+    std::fs::rename(&tmp_path, &path)?;
+    log::info!("Saved session {}", path.to_string_lossy());
+    Ok(path)
+}
+pub fn load(save_directory: &Path, name: &str) -> anyhow::Result<Session> {
+    let path = session_path(save_directory, name)?;
+    let file = std::fs::File::open(&path)
+        .map_err(|e| anyhow::anyhow!("Unable to open session {}: {}", path.to_string_lossy(), e))?;
+    let session: Session = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|e| anyhow::anyhow!("Invalid session {}: {}", path.to_string_lossy(), e))?;
+    if session.version != SESSION_VERSION {
+        anyhow::bail!(
+            "Session {} is version {}, expected {}",
+            path.to_string_lossy(),
+            session.version,
+            SESSION_VERSION
+        );
+    }
+    Ok(session)
+}
+pub fn list(save_directory: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(save_directory) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let file_name = entry.ok()?.file_name();
+            let name = file_name.to_str()?.strip_suffix(SESSION_SUFFIX)?;
+            Some(name.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+// Replaces the nodes, modules and settings with the session's and
+// prepares the graph again. Only events missing from the event
+// cache are replayed.
+pub fn restore(
+    simulation: &Simulation,
+    settings: &mut Settings,
+    session: Session,
+) -> anyhow::Result<()> {
+    let mut bin_interface = simulation.reset_the_bin_interface()?;
+    let erebor = simulation.dwarf_data.lock().unwrap();
+    let mut graph_builder = simulation.graph_builder.lock().unwrap();
+    graph_builder.update_raw_modules(session.modules)?;
+    graph_builder.update_raw_nodes(session.nodes, &erebor)?;
+    graph_builder.prepare(&mut bin_interface, &erebor, 0, &JobProgress::default())?;
+    *settings = Settings {
+        version: settings.version + 1,
+        ..session.settings
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let dir = Path::new("/rec");
+        assert_eq!(
+            session_path(dir, "bug-123_a").unwrap(),
+            PathBuf::from("/rec/bug-123_a.session.json")
+        );
+        assert!(session_path(dir, "").is_err());
+        assert!(session_path(dir, "../frame_time_map").is_err());
+        assert!(session_path(dir, "a b").is_err());
+    }
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("explorant-sessions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut modules = HashMap::new();
+        modules.insert(
+            "app".to_string(),
+            GraphModule {
+                name: "app".into(),
+                parent: Some("".into()),
+                module_attributes: [("collapsed".to_string(), "true".to_string())].into(),
+                origin: Default::default(),
+            },
+        );
+        let session = Session {
+            version: SESSION_VERSION,
+            settings: Settings {
+                selected_node_id: Some(3),
+                ..Settings::default()
+            },
+            modules,
+            nodes: HashMap::new(),
+        };
+        save(&dir, "review", &session).unwrap();
+        save(&dir, "before", &session).unwrap();
+        assert_eq!(load(&dir, "review").unwrap(), session);
+        assert_eq!(list(&dir), vec!["before".to_string(), "review".to_string()]);
+        assert!(load(&dir, "missing").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
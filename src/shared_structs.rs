@@ -11,16 +11,80 @@ use serde::{Deserialize, Serialize};
 // pub...
 //
 macro_rules! pub_struct {
-    ($name:ident {$($field:ident: $t:ty),* $(,)?}) => {
+    ($name:ident {$($(#[$field_meta:meta])* $field:ident: $t:ty),* $(,)?}) => {
         #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)] // ewww
         pub struct $name {
-            $(pub $field: $t),*
+            $($(#[$field_meta])* pub $field: $t),*
         }
     }
 }
 pub type TraceID = usize;
+// tid of a thread in the recorded process
+pub type ThreadID = i32;
+// Used for hits recorded without a thread
+pub const UNKNOWN_THREAD: ThreadID = 0;
+// Returned as JSON with a non 200 status by any endpoint that fails
+pub_struct!(ApiError {
+    kind: ApiErrorKind,
+    message: String,
+    // The annotation the error is about, if any
+    location: Option<LineLocation>,
+});
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiErrorKind {
+    // No such trace, file or session
+    NotFound,
+    // Files that are not sources of the trace
+    Forbidden,
+    // Missing or wrong --auth token
+    Unauthorized,
+    // An annotation that can not be placed or parsed
+    Annotation,
+    // The trace is busy with another job
+    Conflict,
+    // A request that can never succeed, such as a time range that
+    // ends before it starts
+    BadRequest,
+    Internal,
+}
+impl ApiError {
+    pub fn new(kind: ApiErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            location: None,
+        }
+    }
+    pub fn annotation(message: impl Into<String>, location: LineLocation) -> Self {
+        Self {
+            kind: ApiErrorKind::Annotation,
+            message: message.into(),
+            location: Some(location),
+        }
+    }
+}
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(
+                f,
+                "{} ({}:{})",
+                self.message,
+                location.file.to_string_lossy(),
+                location.line_num
+            ),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+// So annotation errors keep their location through anyhow
+impl std::error::Error for ApiError {}
+
 pub_struct!(PingRequest { id: usize });
 pub_struct!(PingResponse { id: usize });
+// Served as /config.js to the frontend
+pub_struct!(FrontendConfig { port: u16 });
 
 pub_struct!(Settings {
     version: usize,
@@ -28,7 +92,14 @@ pub_struct!(Settings {
     use_synoptic: bool,
     show_unreachable_nodes:bool,
     selected_node_id: Option<usize>,
+    // One trace per thread for the inference instead of
+    // one interleaved trace of every thread
+    #[serde(default = "default_partition_by_thread")]
+    partition_by_thread: bool,
 });
+fn default_partition_by_thread() -> bool {
+    true
+}
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -37,6 +108,7 @@ impl Default for Settings {
             show_unreachable_nodes: false,
             use_synoptic: true,
             selected_node_id: None,
+            partition_by_thread: default_partition_by_thread(),
         }
     }
 }
@@ -48,12 +120,56 @@ pub_struct!(InstructionPointerResponse {
     instruction_pointer: usize,
 });
 pub_struct!(AddrOccurrencesRequest{
+    #[serde(default)]
+    trace_id: TraceID,
     synoptic_node_id: usize,
 });
 pub_struct!(AddrOccurrenceResponse{
     val: Vec<TimeStamp>,
+    // captures[i] are the values captured at val[i]
+    #[serde(default)]
+    captures: Vec<Vec<CapturedValue>>,
+});
+pub_struct!(CapturedValue {
+    expression: String,
+    value: String,
+});
+pub_struct!(ThreadLanesRequest {
+    #[serde(default)]
+    trace_id: TraceID,
+});
+pub_struct!(ThreadLanesResponse {
+    lanes: Vec<ThreadLane>,
+});
+// Every event hit by one thread, in order
+pub_struct!(ThreadLane {
+    tid: ThreadID,
+    events: Vec<TimeStamp>,
+});
+pub_struct!(SpansRequest {
+    #[serde(default)]
+    trace_id: TraceID,
+});
+pub_struct!(SpansResponse {
+    spans: Vec<Span>,
+});
+// A span_begin hit and the span_end hit that closed it.
+// Spans are ordered by thread and then by start.
+pub_struct!(Span {
+    name: String,
+    tid: ThreadID,
+    start: TimeStamp,
+    // None if the span never ended
+    end: Option<TimeStamp>,
+    // Number of enclosing spans in the same thread
+    depth: usize,
+    // Wall clock time from the frame time map
+    duration_ms: Option<u128>,
+});
+pub_struct!(AllSourceFilesRequest{
+    #[serde(default)]
+    trace_id: TraceID,
 });
-pub_struct!(AllSourceFilesRequest{});
 pub_struct!(AllSourceFilesResponse{
     files : Vec<PathBuf>
 });
@@ -64,6 +180,8 @@ pub_struct!(AllSourceFilesResponse{
 // This is not synoptic nodes but rather raw nodes. 
 // Modules are the same
 pub_struct!(UpdateRawNodesAndModulesRequest{
+    #[serde(default)]
+    trace_id: TraceID,
     nodes : HashMap<usize,GraphNode>,
     modules : HashMap<String,GraphModule>,
     // 0 => rerun all 
@@ -73,10 +191,131 @@ pub_struct!(UpdateRawNodesAndModulesRequest{
     //require_rerun: bool,
 });
 
+// The graph is prepared by a job, poll /job_status with its id
 pub_struct!(UpdateRawNodesAndModulesResponse{
+    job_id: JobID,
+});
+
+pub type JobID = usize;
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum JobPhase {
+    // Replaying the program to record the events
+    #[default]
+    Replay,
+    // Inferring the graph from the recorded events
+    Synoptic,
+    // Rendering the graph
+    Layout,
+}
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Done,
+    Failed,
+    // The previous graph is kept
+    Cancelled,
+}
+// Pushed to every client of /events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    // The graph of a trace was prepared again
+    Graph { trace_id: TraceID, version: usize },
+    Settings { version: usize },
+    Job(JobStatus),
+    // The coverage of a trace was measured
+    Coverage { trace_id: TraceID },
+}
+pub_struct!(JobStatusRequest{
+    job_id: JobID,
+});
+pub_struct!(CancelJobRequest{
+    job_id: JobID,
+});
+pub_struct!(JobStatus{
+    job_id: JobID,
+    trace_id: TraceID,
+    state: JobState,
+    phase: JobPhase,
+    // Frame time reached by the replay out of the last frame time
+    percent: f64,
+    // Set when the job failed
+    error: Option<ApiError>,
+});
+
+pub_struct!(StartCoverageRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+});
+pub_struct!(StartCoverageResponse{
+    job_id: JobID,
+});
+pub_struct!(CoverageRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+});
+pub_struct!(CoverageResponse{
+    // None until it has been measured
+    coverage: Option<Coverage>,
+});
+// Sorted by file
+pub_struct!(Coverage{
+    files: Vec<FileCoverage>,
+});
+pub_struct!(FileCoverage{
+    file: PathBuf,
+    // line -> times run, for every line in a block of code
+    // that can be recorded
+    lines: BTreeMap<u32, usize>,
+});
+
+// Sessions are saved in the recording of the trace
+pub_struct!(SaveSessionRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+    name: String,
+});
+pub_struct!(SaveSessionResponse{
+    path: PathBuf,
+});
+pub_struct!(LoadSessionRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+    name: String,
+});
+pub_struct!(LoadSessionResponse{
+    settings: Settings,
+});
+pub_struct!(ListSessionsRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+});
+pub_struct!(ListSessionsResponse{
+    names: Vec<String>,
+});
+
+pub_struct!(WriteAnnotationsRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+});
+pub_struct!(WriteAnnotationsResponse{
+    // Files whose annotations were rewritten
+    written: Vec<PathBuf>,
+    // Files left alone because they changed since they were read
+    conflicts: Vec<AnnotationConflict>,
+    // Nodes and modules that have nowhere to be written
+    skipped: Vec<String>,
+});
+pub_struct!(AnnotationConflict{
+    file: PathBuf,
+    reason: String,
 });
 
 pub_struct!(GetRawNodesAndModulesRequest{
+    #[serde(default)]
+    trace_id: TraceID,
 });
 
 pub_struct!(GetRawNodesAndModulesResponse{
@@ -100,11 +339,36 @@ pub_struct!(TraceGeneralInfo {
     id : TraceID,
     frame_time_map: FrameTimeMap,
     proc_maps: Vec<Map>,
+    launch_info: Option<LaunchInfo>,
+});
+
+// How the recorded process was started.
+// Saved next to frame_time_map.json so that
+// a replay knows what the program was given.
+pub_struct!(LaunchInfo {
+    exe: PathBuf,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    stdin: Option<PathBuf>,
 });
+impl LaunchInfo {
+    pub fn new(exe: PathBuf) -> Self {
+        Self {
+            exe,
+            args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+            stdin: None,
+        }
+    }
+}
 pub_struct!(GraphModule{
     name: String,
     parent: Option<String>,
     module_attributes: HashMap<String,String>,
+    #[serde(default)]
+    origin: AnnotationOrigin,
 });
 pub_struct!(GraphNode {
     FQN:String,
@@ -115,23 +379,72 @@ pub_struct!(GraphNode {
     location: LineLocation,
     labeled_transitions: Vec<LabeledTransition>,
     node_attributes: HashMap<String,String>,
+    // Expressions read out of the replay at each hit
+    #[serde(default)]
+    captures: Vec<String>,
+    // Only hits where this condition holds are recorded
+    #[serde(default)]
+    when: Option<String>,
+    // The address was given rather than found from the location
+    #[serde(default)]
+    fixed_address: bool,
+    #[serde(default)]
+    origin: AnnotationOrigin,
 });
+// Where a node or module was declared, so that edits made
+// in the UI can be written back to the annotation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub enum AnnotationOrigin {
+    // Made in the UI and not written anywhere yet
+    #[default]
+    New,
+    // A [[{...}]] comment on this line (1 based) of the file
+    Source { file: PathBuf, line: u32 },
+    // These are never written back
+    Sidecar,
+    Functions,
+}
 pub_struct!(LabeledTransition {
     dest_FQN: String,
     label: String,
 });
-pub_struct!(NodeDataRequest {});
+pub_struct!(NodeDataRequest {
+    #[serde(default)]
+    trace_id: TraceID,
+});
 pub_struct!(NodeDataResponse{
     modules : HashMap<String,GraphModule>,
     nodes : HashMap<usize,GraphNode>,
 });
 
-pub_struct!(CurrentGraphRequest {});
+pub_struct!(CurrentGraphRequest {
+    #[serde(default)]
+    trace_id: TraceID,
+});
 pub_struct!(CurrentGraphResponse {
     version: usize,
     dot: String,
 });
 
+// Serializable form of the inferred graph
+// used by `explorant export --format json`
+pub_struct!(GraphExport {
+    modules: HashMap<String, GraphModule>,
+    nodes: Vec<ExportedNode>,
+    edges: Vec<ExportedEdge>,
+});
+pub_struct!(ExportedNode {
+    id: i64,
+    label: String,
+    // None for the INITIAL and TERMINAL nodes
+    node: Option<GraphNode>,
+});
+pub_struct!(ExportedEdge {
+    source: i64,
+    target: i64,
+    probability: Option<f32>,
+});
+
 pub_struct!(ScreenshotCaptures {});
 pub_struct!(TimeRange {
     start: TimeStamp,
@@ -154,6 +467,8 @@ impl TimeStamp {
     }
 }
 pub_struct!(CreateGdbServerRequest {
+    #[serde(default)]
+    trace_id: TraceID,
     start_time : TimeStamp,
 });
 
@@ -161,15 +476,32 @@ pub_struct!(CreateGdbServerResponse{
     value: String, 
 });
 
-pub_struct!(SourceFileRequest { file_name: String });
+pub_struct!(SourceFileRequest {
+    #[serde(default)]
+    trace_id: TraceID,
+    file_name: String,
+});
 
 pub_struct!(SourceFileResponse { data: String });
 
 pub_struct!(GetFunctionData {});
-pub_struct!(FunctionTimeRangeRequest { range: TimeRange });
+pub_struct!(FunctionTimeRangeRequest {
+    #[serde(default)]
+    trace_id: TraceID,
+    range: TimeRange,
+});
+pub_struct!(StartFunctionTimeRangeResponse{
+    job_id: JobID,
+});
 pub_struct!(FunctionTimeRangeResponse{
     addr_of_called_functions:Vec<usize>,
 });
+// The result of a job that answers a query
+pub_struct!(JobResultRequest{
+    #[serde(default)]
+    trace_id: TraceID,
+    job_id: JobID,
+});
 
 pub_struct!(FunctionInfoRequest {
     addr_of_function: usize,
@@ -216,18 +548,30 @@ pub_struct!(Function {
     size: usize,
     start_line: u32,
     end_line: u32,
+    // Addresses of the ret instructions in the function
+    #[serde(default)]
+    return_addresses: Vec<usize>,
 });
 
 pub_struct!(FunctionExecutionHeatMapRequest {
+    #[serde(default)]
+    trace_id: TraceID,
     range: TimeRange,
+    // Any address in the function
     function_address: usize,
 });
+pub_struct!(StartFunctionHeatMapResponse{
+    job_id: JobID,
+});
 pub_struct!(FrameExecutionHeatMapResponse {
     map: FunctionExecutionHeatMap,
 });
 
 pub_struct!(FunctionExecutionHeatMap{
+    // Where the lines of the function are
+    source_file: PathBuf,
     addr_vs_times_executed: HashMap<usize,usize>,
+    line_vs_times_executed: BTreeMap<u32,usize>,
 });
 
 pub_struct!( FrameTimeMap {
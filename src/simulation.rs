@@ -12,13 +12,21 @@ use std::io::{self, BufRead, BufReader};
 // use symbolic_demangle::{Demangle, DemangleOptions};
 
 use crate::gdb_instance_manager::GdbInstanceManager;
-use crate::shared_structs::LineLocation;
+use crate::recorder::LAUNCH_INFO_FILE_NAME;
+use crate::shared_structs::{
+    Coverage, FrameExecutionHeatMapResponse, FunctionTimeRangeResponse, JobID, LaunchInfo,
+    LineLocation,
+};
 use crate::{
     erebor::Erebor,
     graph_builder::GraphBuilder,
     shared_structs::{FrameTimeMap, GraphNode},
     trampoline::{TrampolineManager, TrampolineStackInfo},
 };
+use crate::config::{ProjectConfig, SubstitutePath};
+use crate::file_parsing::FunctionPattern;
+use crate::jobs::JobProgress;
+use crate::pack::{self, PathRemap};
 use crate::{file_parsing, main};
 
 // always aquire the locks in the order
@@ -35,8 +43,19 @@ pub struct Simulation {
     // pub symbol_table: Mutex<Vec<(String, object::Symbol<'static,'static>)>>,
     pub last_rip: Mutex<usize>,
     pub save_directory: PathBuf,
+    // None for recordings made before launch info was saved
+    pub launch_info: Option<LaunchInfo>,
     pub dwarf_data: Mutex<Erebor>,
+    // The build id of the recorded binary, or a hash of it without one.
+    // Its path is no use: pack copies the binary into every trace.
+    pub binary_id: Vec<u8>,
     pub graph_builder: Mutex<GraphBuilder>,
+    // None until a coverage job has measured it
+    pub coverage: Mutex<Option<Coverage>>,
+    // The last function time range measured and the job that did
+    pub function_time_range: Mutex<Option<(JobID, FunctionTimeRangeResponse)>>,
+    // Likewise for heat maps
+    pub heat_map: Mutex<Option<(JobID, FrameExecutionHeatMapResponse)>>,
 }
 // SAFETY: const *cxx:void is not send and sync
 // because if a thread context switches while running
@@ -61,15 +80,35 @@ unsafe impl Sync for Simulation {}
 //     Ok(to_ret)
 // }
 
+fn binary_id(obj_file: &object::File, data: &[u8]) -> Vec<u8> {
+    match obj_file.build_id() {
+        Ok(Some(build_id)) => build_id.to_vec(),
+        // FNV-1a
+        _ => data
+            .iter()
+            .fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+            })
+            .to_le_bytes()
+            .to_vec(),
+    }
+}
+
 impl Simulation {
-    pub fn reset_the_bin_interface(&self) -> anyhow::Result<BinaryInterface> {
+    // A fresh replay session of the trace in directory
+    // stopped at target_event
+    pub fn open_bin_interface(directory: &PathBuf, target_event: i64) -> BinaryInterface {
         let mut bin_interface =
-            BinaryInterface::new_at_target_event(0, self.save_directory.clone());
+            BinaryInterface::new_at_target_event(target_event, directory.clone());
         let cthread = bin_interface.get_current_thread();
         bin_interface.pin_mut().set_query_thread(cthread);
         bin_interface.set_pass_signals(vec![
             0, 0xe, 0x14, 0x17, 0x1a, 0x1b, 0x1c, 0x21, 0x24, 0x25, 0x2c, 0x4c, 0x97,
         ]);
+        bin_interface
+    }
+    pub fn reset_the_bin_interface(&self) -> anyhow::Result<BinaryInterface> {
+        let bin_interface = Self::open_bin_interface(&self.save_directory, 0);
         //let rip = bin_interface
         //    .get_register(GdbRegister::DREG_RIP, bin_interface.get_current_thread())
         //    .to_usize();
@@ -92,30 +131,22 @@ impl Simulation {
 
         Ok(bin_interface)
     }
-    pub fn new(directory: PathBuf, offset_addrs_with_map: bool) -> anyhow::Result<Self> {
-        let mut bin_interface = BinaryInterface::new_at_target_event(0, directory.clone());
-        let cthread = bin_interface.get_current_thread();
-        bin_interface.pin_mut().set_query_thread(cthread);
-        bin_interface.set_pass_signals(vec![
-            0, 0xe, 0x14, 0x17, 0x1a, 0x1b, 0x1c, 0x21, 0x24, 0x25, 0x2c, 0x4c, 0x97,
-        ]);
+    pub fn new(
+        directory: PathBuf,
+        offset_addrs_with_map: bool,
+        function_patterns: &[FunctionPattern],
+        substitute_paths: &[SubstitutePath],
+    ) -> anyhow::Result<Self> {
+        // Bundles made by `pack` are explored from where they unpack
+        let directory = pack::open_trace(&directory)?;
+        let remap = PathRemap::load(&directory)?;
+        let mut bin_interface = Self::open_bin_interface(&directory, 0);
         let rip = bin_interface
             .get_register(GdbRegister::DREG_RIP, bin_interface.get_current_thread())
             .to_usize();
         dbg!(rip);
 
-        let mut stack_info = TrampolineStackInfo {
-            base_addr: 0x71000000,
-            // Ive had success with 65KiB
-            // but I made it 256 MiB just in case.
-            // This shouldn't overflow
-            //
-            //NOTE:
-            //  This is consistently faster on my machine if
-            //  it is given 1GiB instead of 256MiB.
-            size: 0x10000000,
-            reserved_space: 0x40,
-        };
+        let mut stack_info = TrampolineStackInfo::default();
         stack_info.allocate_map(&mut bin_interface);
         stack_info.setup_stack_ptr(&mut bin_interface).unwrap();
         // dbg!(bin_interface.get_proc_map());
@@ -133,13 +164,24 @@ impl Simulation {
             }
         }
         // Read symbol file and parse symbols
-        let symbol_file = bin_interface.get_exec_file();
+        let mut symbol_file = PathBuf::from(bin_interface.get_exec_file());
+        if let Some(local) = remap.as_ref().and_then(|remap| remap.remap(&symbol_file)) {
+            symbol_file = local;
+        }
         dbg!(&symbol_file);
 
         let symbol_str = std::fs::read(symbol_file).unwrap();
         let obj_file = object::File::parse(&*symbol_str).unwrap();
+        let binary_id = binary_id(&obj_file, &symbol_str);
         //
-        let dwarf_data = Erebor::new(obj_file, special_map.expect("Unable to find procmap that correlates with executable file."), offset_addrs_with_map);
+        let mut dwarf_data = Erebor::new(obj_file, special_map.expect("Unable to find procmap that correlates with executable file."), offset_addrs_with_map);
+        if let Some(remap) = &remap {
+            dwarf_data.remap_files(|path| remap.remap(path));
+        }
+        // Rules from the command line come before the config file's
+        let config = ProjectConfig::load(&directory)?;
+        let rules = substitute_paths.iter().cloned().chain(config.substitute_path).collect();
+        dwarf_data.substitute_paths(rules);
 
         let frame_time_map: FrameTimeMap = {
             let file = std::fs::File::open(directory.join("frame_time_map.json"))?;
@@ -147,15 +189,25 @@ impl Simulation {
 
             serde_json::from_reader(reader)?
         };
+        let launch_info: Option<LaunchInfo> = match std::fs::File::open(directory.join(LAUNCH_INFO_FILE_NAME)) {
+            Ok(file) => Some(serde_json::from_reader(std::io::BufReader::new(file))?),
+            Err(_) => None,
+        };
 
         let trampoline_manager = TrampolineManager::new(&mut bin_interface, stack_info, &proc_map);
         let max_ft = frame_time_map.times.keys().max().unwrap();
 
-        let mut g_builder = GraphBuilder::new((*max_ft) as usize);
-        file_parsing::parse_annotations(&dwarf_data, &mut g_builder)?;
+        let mut g_builder = GraphBuilder::new((*max_ft) as usize, directory.clone());
+        let sidecar = file_parsing::find_sidecar(&directory, launch_info.as_ref());
+        file_parsing::parse_annotations(
+            &dwarf_data,
+            &mut g_builder,
+            function_patterns,
+            sidecar.as_deref(),
+        )?;
         dbg!(&g_builder.nodes);
         dbg!(&g_builder.modules);
-        g_builder.prepare(&mut bin_interface, 0)?;
+        g_builder.prepare(&mut bin_interface, &dwarf_data, 0, &JobProgress::default())?;
 
         Ok(Self {
             bin_interface: Mutex::new(bin_interface),
@@ -164,9 +216,14 @@ impl Simulation {
             frame_time_map: Mutex::new(frame_time_map),
             last_rip: Mutex::new(rip),
             save_directory: directory,
+            launch_info,
             dwarf_data: Mutex::new(dwarf_data),
+            binary_id,
             graph_builder: Mutex::new(g_builder),
             gdb_instance_mgr: Mutex::new(GdbInstanceManager::default()),
+            coverage: Mutex::new(None),
+            function_time_range: Mutex::new(None),
+            heat_map: Mutex::new(None),
             // symbol_table:Mutex::new(symbols),
         })
     }
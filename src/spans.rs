@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::shared_structs::{Span, ThreadID, TimeStamp};
+
+// Pairs the span_begin and span_end hits of each thread into spans.
+//
+// Spans nest per thread: an end closes the innermost open span with
+// the same name. Spans opened inside it that are still open never
+// get an end, the same as spans still open when the program exits.
+// An end without an open span is ignored.
+//
+// span_of returns the span name of an address and whether it begins
+// the span, or None for addresses that are not part of a span.
+pub fn pair_spans(
+    lanes: BTreeMap<ThreadID, Vec<TimeStamp>>,
+    span_of: impl Fn(usize) -> Option<(String, bool)>,
+    times: &HashMap<i64, u128>,
+) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    for (tid, stamps) in lanes {
+        // indexes into spans
+        let mut open: Vec<usize> = Vec::new();
+        for stamp in stamps {
+            let Some((name, is_begin)) = stamp.addr.and_then(&span_of) else {
+                continue;
+            };
+            if is_begin {
+                open.push(spans.len());
+                spans.push(Span {
+                    name,
+                    tid,
+                    start: stamp,
+                    end: None,
+                    depth: open.len() - 1,
+                    duration_ms: None,
+                });
+                continue;
+            }
+            let Some(position) = open.iter().rposition(|i| spans[*i].name == name) else {
+                log::warn!("Span {} ended without beginning in thread {}", name, tid);
+                continue;
+            };
+            let span = &mut spans[open[position]];
+            open.truncate(position);
+            span.duration_ms = duration_ms(times, &span.start, &stamp);
+            span.end = Some(stamp);
+        }
+    }
+    spans
+}
+
+// Wall clock time between the frame times of two stamps. Hits in the
+// same frame time have no measurable duration between them.
+fn duration_ms(times: &HashMap<i64, u128>, start: &TimeStamp, end: &TimeStamp) -> Option<u128> {
+    let start = times.get(&(start.frame_time as i64))?;
+    let end = times.get(&(end.frame_time as i64))?;
+    end.checked_sub(*start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEGIN_A: usize = 1;
+    const END_A: usize = 2;
+    const BEGIN_B: usize = 3;
+    const END_B: usize = 4;
+    const EVENT: usize = 5;
+
+    fn span_of(addr: usize) -> Option<(String, bool)> {
+        match addr {
+            BEGIN_A => Some(("a".into(), true)),
+            END_A => Some(("a".into(), false)),
+            BEGIN_B => Some(("b".into(), true)),
+            END_B => Some(("b".into(), false)),
+            _ => None,
+        }
+    }
+    fn lane(hits: &[(usize, usize)]) -> Vec<TimeStamp> {
+        hits.iter()
+            .map(|(frame_time, addr)| TimeStamp {
+                frame_time: *frame_time,
+                addr: Some(*addr),
+                instance_of_addr: Some(1),
+            })
+            .collect()
+    }
+    fn times() -> HashMap<i64, u128> {
+        (1..10).map(|ft| (ft, 1000 + 10 * ft as u128)).collect()
+    }
+
+    #[test]
+    fn nested() {
+        let mut lanes = BTreeMap::new();
+        lanes.insert(
+            7,
+            lane(&[(1, BEGIN_A), (2, BEGIN_B), (3, EVENT), (4, END_B), (6, END_A)]),
+        );
+        let spans = pair_spans(lanes, span_of, &times());
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].name.as_str(), spans[0].depth), ("a", 0));
+        assert_eq!((spans[1].name.as_str(), spans[1].depth), ("b", 1));
+        assert_eq!(spans[0].duration_ms, Some(50));
+        assert_eq!(spans[1].duration_ms, Some(20));
+        assert_eq!(spans[1].end.as_ref().unwrap().frame_time, 4);
+        assert!(spans.iter().all(|span| span.tid == 7));
+    }
+    #[test]
+    fn unbalanced() {
+        let mut lanes = BTreeMap::new();
+        // b is never closed, the stray end is ignored and the
+        // second a is still open at exit
+        lanes.insert(
+            1,
+            lane(&[(1, END_B), (1, BEGIN_A), (2, BEGIN_B), (3, END_A), (4, BEGIN_A)]),
+        );
+        // Threads are paired separately
+        lanes.insert(2, lane(&[(5, END_A)]));
+        let spans = pair_spans(lanes, span_of, &times());
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].duration_ms, Some(20));
+        assert!(spans[1].end.is_none());
+        assert_eq!(spans[2].depth, 0);
+        assert!(spans[2].end.is_none());
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+// Native replacement for the Synoptic model inference tool
+// (https://github.com/ModelInference/synoptic).
+//
+// Like Synoptic, the model is a graph where every node is a
+// partition of event instances that share a label. Instances are
+// partitioned by their label and the next k labels that follow them
+// (k-tails). Temporal invariants are mined from the input traces and
+// k is increased until the model no longer allows a path that
+// violates one of them.
+//
+// The graph always has a single INITIAL and a single TERMINAL node.
+pub const INITIAL_LABEL: &str = "INITIAL";
+pub const TERMINAL_LABEL: &str = "TERMINAL";
+// Past this the graph is mostly a copy of the traces
+// and no longer useful to look at.
+pub const DEFAULT_MAX_K: usize = 4;
+
+const INITIAL_ID: i64 = 0;
+const TERMINAL_ID: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredNode {
+    pub id: i64,
+    pub label: String,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferredEdge {
+    pub source: i64,
+    pub target: i64,
+    // Fraction of the transitions out of source that go to target
+    pub probability: f32,
+}
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct InferredGraph {
+    pub nodes: Vec<InferredNode>,
+    pub edges: Vec<InferredEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Invariant {
+    // Every a is eventually followed by a b
+    AlwaysFollowedBy(String, String),
+    // No a is ever followed by a b
+    NeverFollowedBy(String, String),
+    // Every b is preceded by an a
+    AlwaysPrecedes(String, String),
+}
+
+// Infer a graph from a set of traces. Each trace is one
+// independent execution (Synoptic's "partition").
+pub fn infer(traces: &[Vec<&str>], max_k: usize) -> anyhow::Result<InferredGraph> {
+    for trace in traces {
+        if let Some(label) = trace
+            .iter()
+            .find(|l| **l == INITIAL_LABEL || **l == TERMINAL_LABEL)
+        {
+            anyhow::bail!("{} is a reserved event name", label);
+        }
+    }
+    let invariants = mine_invariants(traces);
+    for k in 0..=max_k {
+        let model = Model::build(traces, k);
+        let violated = invariants.iter().find(|inv| !model.satisfies(inv));
+        match violated {
+            None => return Ok(model.into_graph()),
+            Some(inv) if k == max_k => {
+                log::warn!(
+                    "Model inference stopped at k={} with the invariant {:?} still violated",
+                    k,
+                    inv
+                );
+                return Ok(model.into_graph());
+            }
+            Some(_) => {}
+        }
+    }
+    unreachable!()
+}
+
+pub fn mine_invariants(traces: &[Vec<&str>]) -> BTreeSet<Invariant> {
+    let labels: BTreeSet<&str> = traces.iter().flatten().copied().collect();
+    // per trace: label -> (first index, last index)
+    let positions: Vec<HashMap<&str, (usize, usize)>> = traces
+        .iter()
+        .map(|trace| {
+            let mut pos: HashMap<&str, (usize, usize)> = HashMap::new();
+            for (i, label) in trace.iter().enumerate() {
+                pos.entry(label).and_modify(|p| p.1 = i).or_insert((i, i));
+            }
+            pos
+        })
+        .collect();
+
+    let mut invariants = BTreeSet::new();
+    for a in &labels {
+        for b in &labels {
+            let always_followed = positions.iter().all(|pos| match (pos.get(a), pos.get(b)) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(pa), Some(pb)) => pb.1 > pa.1,
+            });
+            let never_followed = positions.iter().all(|pos| match (pos.get(a), pos.get(b)) {
+                (Some(pa), Some(pb)) => pa.0 >= pb.1,
+                _ => true,
+            });
+            let always_precedes = positions.iter().all(|pos| match (pos.get(a), pos.get(b)) {
+                (_, None) => true,
+                (None, Some(_)) => false,
+                (Some(pa), Some(pb)) => pa.0 < pb.0,
+            });
+            if always_followed {
+                invariants.insert(Invariant::AlwaysFollowedBy(a.to_string(), b.to_string()));
+            }
+            if never_followed {
+                invariants.insert(Invariant::NeverFollowedBy(a.to_string(), b.to_string()));
+            }
+            if always_precedes {
+                invariants.insert(Invariant::AlwaysPrecedes(a.to_string(), b.to_string()));
+            }
+        }
+    }
+    invariants
+}
+
+struct Model {
+    // id -> label
+    labels: Vec<String>,
+    // source -> target -> number of transitions
+    transitions: Vec<BTreeMap<usize, usize>>,
+}
+
+impl Model {
+    fn build(traces: &[Vec<&str>], k: usize) -> Self {
+        // Collect the partition keys first so that node ids
+        // only depend on the traces and not on hashing order
+        let mut keys: BTreeSet<Vec<&str>> = BTreeSet::new();
+        for trace in traces {
+            for i in 0..trace.len() {
+                keys.insert(Self::key(trace, i, k));
+            }
+        }
+        let mut labels = vec![INITIAL_LABEL.to_string(), TERMINAL_LABEL.to_string()];
+        let mut ids: HashMap<Vec<&str>, usize> = HashMap::new();
+        for key in keys {
+            ids.insert(key.clone(), labels.len());
+            labels.push(key[0].to_string());
+        }
+        let mut transitions = vec![BTreeMap::new(); labels.len()];
+        for trace in traces {
+            let mut previous = INITIAL_ID as usize;
+            for i in 0..trace.len() {
+                let current = ids[&Self::key(trace, i, k)];
+                *transitions[previous].entry(current).or_insert(0) += 1;
+                previous = current;
+            }
+            *transitions[previous]
+                .entry(TERMINAL_ID as usize)
+                .or_insert(0) += 1;
+        }
+        Self {
+            labels,
+            transitions,
+        }
+    }
+    // The label of the event followed by its k-tail
+    fn key<'a>(trace: &[&'a str], i: usize, k: usize) -> Vec<&'a str> {
+        let mut key = vec![trace[i]];
+        for j in (i + 1)..=(i + k) {
+            match trace.get(j) {
+                Some(label) => key.push(label),
+                None => {
+                    key.push(TERMINAL_LABEL);
+                    break;
+                }
+            }
+        }
+        key
+    }
+    // Every node reachable from start through at least one transition
+    // without passing through a blocked node
+    fn reachable(&self, start: &[usize], blocked: &dyn Fn(usize) -> bool) -> HashSet<usize> {
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<usize> = start.iter().copied().collect();
+        while let Some(node) = queue.pop_front() {
+            for next in self.transitions[node].keys() {
+                if seen.insert(*next) && !blocked(*next) {
+                    queue.push_back(*next);
+                }
+            }
+        }
+        seen
+    }
+    fn nodes_with_label(&self, label: &str) -> Vec<usize> {
+        (0..self.labels.len())
+            .filter(|id| *id > TERMINAL_ID as usize && self.labels[*id] == label)
+            .collect()
+    }
+    fn satisfies(&self, invariant: &Invariant) -> bool {
+        match invariant {
+            Invariant::AlwaysFollowedBy(a, b) => {
+                let reached = self.reachable(&self.nodes_with_label(a), &|id| self.labels[id] == *b);
+                !reached.contains(&(TERMINAL_ID as usize))
+            }
+            Invariant::NeverFollowedBy(a, b) => {
+                let reached = self.reachable(&self.nodes_with_label(a), &|_| false);
+                !reached
+                    .iter()
+                    .any(|id| *id > TERMINAL_ID as usize && self.labels[*id] == *b)
+            }
+            Invariant::AlwaysPrecedes(a, b) => {
+                let reached =
+                    self.reachable(&[INITIAL_ID as usize], &|id| self.labels[id] == *a);
+                !reached
+                    .iter()
+                    .any(|id| *id > TERMINAL_ID as usize && self.labels[*id] == *b)
+            }
+        }
+    }
+    fn into_graph(self) -> InferredGraph {
+        let nodes = self
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(id, label)| InferredNode {
+                id: id as i64,
+                label: label.clone(),
+            })
+            .collect();
+        let mut edges = Vec::new();
+        for (source, targets) in self.transitions.iter().enumerate() {
+            let total: usize = targets.values().sum();
+            for (target, count) in targets {
+                edges.push(InferredEdge {
+                    source: source as i64,
+                    target: *target as i64,
+                    probability: *count as f32 / total as f32,
+                });
+            }
+        }
+        InferredGraph { nodes, edges }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label_of(graph: &InferredGraph, id: i64) -> &str {
+        &graph.nodes.iter().find(|n| n.id == id).unwrap().label
+    }
+    fn edge_labels(graph: &InferredGraph) -> Vec<(String, String, f32)> {
+        let mut edges: Vec<(String, String, f32)> = graph
+            .edges
+            .iter()
+            .map(|e| {
+                (
+                    label_of(graph, e.source).to_string(),
+                    label_of(graph, e.target).to_string(),
+                    e.probability,
+                )
+            })
+            .collect();
+        edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        edges
+    }
+
+    #[test]
+    fn empty_trace() {
+        let graph = infer(&[vec![]], DEFAULT_MAX_K).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(
+            edge_labels(&graph),
+            vec![(INITIAL_LABEL.into(), TERMINAL_LABEL.into(), 1.0)]
+        );
+    }
+    #[test]
+    fn reserved_names() {
+        assert!(infer(&[vec!["a", INITIAL_LABEL]], DEFAULT_MAX_K).is_err());
+    }
+    #[test]
+    fn loop_collapses() {
+        let trace = vec!["init", "print", "print", "print", "print"];
+        let graph = infer(&[trace], DEFAULT_MAX_K).unwrap();
+        // INITIAL, TERMINAL, init, print
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(
+            edge_labels(&graph),
+            vec![
+                ("INITIAL".into(), "init".into(), 1.0),
+                ("init".into(), "print".into(), 1.0),
+                ("print".into(), "TERMINAL".into(), 0.25),
+                ("print".into(), "print".into(), 0.75),
+            ]
+        );
+    }
+    #[test]
+    fn probabilities_across_traces() {
+        let graph = infer(&[vec!["a", "b"], vec!["a", "c"]], DEFAULT_MAX_K).unwrap();
+        let edges = edge_labels(&graph);
+        assert!(edges.contains(&("a".into(), "b".into(), 0.5)));
+        assert!(edges.contains(&("a".into(), "c".into(), 0.5)));
+    }
+    #[test]
+    fn mined_invariants() {
+        let traces = vec![vec!["open", "read", "close"], vec!["open", "close"]];
+        let invariants = mine_invariants(&traces);
+        assert!(invariants.contains(&Invariant::AlwaysFollowedBy(
+            "open".into(),
+            "close".into()
+        )));
+        assert!(invariants.contains(&Invariant::AlwaysPrecedes(
+            "open".into(),
+            "read".into()
+        )));
+        assert!(invariants.contains(&Invariant::NeverFollowedBy(
+            "close".into(),
+            "open".into()
+        )));
+        assert!(!invariants.contains(&Invariant::AlwaysFollowedBy(
+            "open".into(),
+            "read".into()
+        )));
+    }
+    #[test]
+    fn refines_to_satisfy_invariants() {
+        // With k=0 both x nodes merge and the model allows
+        // a -> x -> d which was never observed
+        let traces = vec![vec!["a", "x", "b"], vec!["c", "x", "d"]];
+        let graph = infer(&traces, DEFAULT_MAX_K).unwrap();
+        let xs = graph.nodes.iter().filter(|n| n.label == "x").count();
+        assert_eq!(xs, 2);
+        let invariants = mine_invariants(&traces);
+        assert!(invariants.contains(&Invariant::NeverFollowedBy("a".into(), "d".into())));
+    }
+    #[test]
+    fn deterministic_ids() {
+        let traces = vec![vec!["a", "b", "a", "c"], vec!["b", "c"]];
+        assert_eq!(
+            infer(&traces, DEFAULT_MAX_K).unwrap(),
+            infer(&traces, DEFAULT_MAX_K).unwrap()
+        );
+    }
+}
@@ -31,6 +31,22 @@ pub struct TrampolineStackInfo {
     pub size: usize,
     pub reserved_space: usize,
 }
+impl Default for TrampolineStackInfo {
+    fn default() -> Self {
+        Self {
+            base_addr: 0x71000000,
+            // Ive had success with 65KiB
+            // but I made it 256 MiB just in case.
+            // This shouldn't overflow
+            //
+            //NOTE:
+            //  This is consistently faster on my machine if
+            //  it is given 1GiB instead of 256MiB.
+            size: 0x10000000,
+            reserved_space: 0x40,
+        }
+    }
+}
 impl TrampolineStackInfo {
     pub fn allocate_map(&self, binary_interface: &mut BinaryInterface) {
         binary_interface
@@ -166,6 +182,14 @@ impl TrampolineManager {
         heap_info
     }
 
+    // The address of every instruction replaced by a trampoline
+    pub fn instrumented_addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.trampoline_maps
+            .values()
+            .flat_map(|heap| heap.allocations.iter())
+            .flat_map(|allocation| &allocation.val.replaced_instructions)
+            .map(|instr| instr.ip() as usize)
+    }
     pub fn recorded_addresses(&self) -> &Vec<usize> {
         &self.recorded_addresses
     }
@@ -235,14 +259,9 @@ impl TrampolineManager {
         &mut self,
         bin_interface: &mut BinaryInterface,
     ) -> Result<(), Box<dyn Error>> {
-        self.trampoline_maps
-            .iter_mut()
-            .map(|(map, heap)| {
-                Self::create_trampolines_for_map(&self.stack_info, bin_interface, map, heap)
-                    .unwrap();
-                (map, heap)
-            })
-            .count();
+        for (map, heap) in self.trampoline_maps.iter_mut() {
+            Self::create_trampolines_for_map(&self.stack_info, bin_interface, map, heap)?;
+        }
         Ok(())
     }
     fn create_trampolines_for_map(
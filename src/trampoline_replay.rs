@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use librr_rs::*;
+use procmaps::Map;
+use rust_lapper::{Interval, Lapper};
+
+use crate::erebor::Erebor;
+use crate::jobs::JobProgress;
+use crate::shared_structs::{
+    ApiError, ApiErrorKind, Function, FunctionExecutionHeatMap, TimeRange, TimeStamp,
+};
+use crate::simulation::Simulation;
+use crate::trampoline::{TrampolineManager, TrampolineStackInfo};
+
+// Replays a trace with a trampoline on the control flow instructions
+// of every executable map. Each trampoline pushes the address of its
+// instruction onto the trampoline stack, which is drained every time
+// the replay stops. Only instructions long enough to be replaced by
+// the jump are recorded, see create_trampolines_for_map.
+
+pub struct Executed {
+    // Every instruction with a trampoline
+    pub instrumented: Vec<usize>,
+    // The instrumented instructions run, once per execution
+    pub addresses: Vec<usize>,
+}
+
+// The instructions executed in range. None replays the whole recording.
+pub fn record_executed(
+    directory: &Path,
+    range: Option<&TimeRange>,
+    progress: &JobProgress,
+) -> anyhow::Result<Executed> {
+    if let Some(range) = range {
+        if range.start.frame_time > range.end.frame_time {
+            return Err(bad_range("The end of the range comes before its start").into());
+        }
+    }
+    // As for gdb servers, a TimeStamp without an address is the
+    // state right after the event before its frame time
+    let start_event = range.map_or(0, |range| range.start.frame_time.saturating_sub(1));
+    let mut bin_interface =
+        Simulation::open_bin_interface(&directory.to_path_buf(), start_event as i64);
+    if let Some(start) = range
+        .map(|range| &range.start)
+        .filter(|start| start.addr.is_some())
+    {
+        if !run_to(&mut bin_interface, Some(start), None, progress)? {
+            return Err(bad_range("The start of the range is not in the recording").into());
+        }
+    }
+    // Instrumented only now so the replay to the start is not slowed down
+    let mut manager = instrument(&mut bin_interface)?;
+    let end = range.map(|range| &range.end);
+    if !run_to(&mut bin_interface, end, Some(&mut manager), progress)? {
+        return Err(
+            bad_range("The end of the range is not in the recording after its start").into(),
+        );
+    }
+    Ok(Executed {
+        instrumented: manager.instrumented_addresses().collect(),
+        addresses: std::mem::take(&mut manager.recorded_addresses),
+    })
+}
+
+// The start address of each function that one of addresses is in
+pub fn called_functions(erebor: &Erebor, addresses: &[usize]) -> Vec<usize> {
+    let distinct: BTreeSet<usize> = addresses.iter().copied().collect();
+    let functions: BTreeSet<usize> = distinct
+        .into_iter()
+        .filter_map(|addr| erebor.function_at(addr))
+        .map(|func| func.address)
+        .collect();
+    functions.into_iter().collect()
+}
+
+// How often each recorded address in function was executed and,
+// through the line table of its file, each of its lines.
+// Only the control flow instruction that ends a basic block is
+// recorded, so every line of the block gets its count, see blocks.
+pub fn heat_map(
+    erebor: &Erebor,
+    function: &Function,
+    executed: &Executed,
+) -> FunctionExecutionHeatMap {
+    let code = function.address..function.address + function.size;
+    // Start address of each line table row in the function -> line
+    let rows: BTreeMap<usize, u32> = erebor
+        .files
+        .get(&function.source_file)
+        .into_iter()
+        .flat_map(|file| &file.lines)
+        .flat_map(|(line, addrs)| addrs.iter().map(move |addr| (*addr, *line)))
+        .filter(|(addr, _)| code.contains(addr))
+        .collect();
+    let mut addr_vs_times_executed = HashMap::new();
+    for addr in executed.addresses.iter().filter(|addr| code.contains(addr)) {
+        *addr_vs_times_executed.entry(*addr).or_insert(0) += 1;
+    }
+    let block_ends: BTreeSet<usize> = executed
+        .instrumented
+        .iter()
+        .chain(addr_vs_times_executed.keys())
+        .copied()
+        .collect();
+    let mut line_vs_times_executed = BTreeMap::new();
+    for (block, count) in blocks(function, &block_ends, &addr_vs_times_executed) {
+        if count == 0 {
+            continue;
+        }
+        let lines: BTreeSet<u32> = block_rows(&rows, &block).copied().collect();
+        for line in lines {
+            *line_vs_times_executed.entry(line).or_insert(0) += count;
+        }
+    }
+    FunctionExecutionHeatMap {
+        source_file: function.source_file.clone(),
+        addr_vs_times_executed,
+        line_vs_times_executed,
+    }
+}
+
+// The blocks of code in function and the times each ran. A block ends
+// at an instrumented instruction and is taken to start after the
+// previous one, or at the start of the function: when a jump lands in
+// between or an instruction had no trampoline, two blocks are counted
+// as one.
+pub fn blocks(
+    function: &Function,
+    block_ends: &BTreeSet<usize>,
+    counts: &HashMap<usize, usize>,
+) -> Vec<(RangeInclusive<usize>, usize)> {
+    let mut blocks = Vec::new();
+    let mut start = function.address;
+    for end in block_ends.range(function.address..function.address + function.size) {
+        blocks.push((start..=*end, counts.get(end).copied().unwrap_or(0)));
+        start = end + 1;
+    }
+    blocks
+}
+
+// The rows of a line table, keyed by start address, that a block
+// covers: the one it starts in and any that begin in it
+pub fn block_rows<'a, T>(
+    rows: &'a BTreeMap<usize, T>,
+    block: &RangeInclusive<usize>,
+) -> impl Iterator<Item = &'a T> {
+    rows.range(..=*block.start())
+        .next_back()
+        .into_iter()
+        .chain(rows.range(block.clone()))
+        .map(|(_, row)| row)
+}
+
+fn instrument(bin_interface: &mut BinaryInterface) -> anyhow::Result<TrampolineManager> {
+    let mut stack_info = TrampolineStackInfo::default();
+    stack_info.allocate_map(bin_interface);
+    stack_info
+        .setup_stack_ptr(bin_interface)
+        .map_err(|e| anyhow::anyhow!("Unable to set up the trampoline stack: {}", e))?;
+    let mut proc_map: Lapper<usize, Map> = Lapper::new(vec![]);
+    let mappings = bin_interface
+        .get_proc_map()
+        .map_err(|e| anyhow::anyhow!("Unable to read the proc maps: {}", e))?;
+    for map in mappings.iter() {
+        proc_map.insert(Interval {
+            start: map.base,
+            stop: map.ceiling,
+            val: map.clone(),
+        });
+    }
+    let mut manager = TrampolineManager::new(bin_interface, stack_info, &proc_map);
+    manager
+        .create_trampolines(bin_interface)
+        .map_err(|e| anyhow::anyhow!("Unable to create the trampolines: {}", e))?;
+    Ok(manager)
+}
+
+// Continues until target or, without one, until the program exits.
+// Returns whether target was reached. With a manager the trampoline
+// stack is drained into it at every stop.
+fn run_to(
+    bin_interface: &mut BinaryInterface,
+    target: Option<&TimeStamp>,
+    mut manager: Option<&mut TrampolineManager>,
+    progress: &JobProgress,
+) -> anyhow::Result<bool> {
+    let cont = GdbContAction {
+        type_: GdbActionType::ACTION_CONTINUE,
+        target: bin_interface.get_current_thread(),
+        signal_to_deliver: 0,
+    };
+    let target_addr = target.and_then(|target| target.addr);
+    if let Some(addr) = target_addr {
+        bin_interface.pin_mut().set_sw_breakpoint(addr, 1);
+    }
+    let mut times_reached = 0;
+    let reached = loop {
+        progress.check_cancelled()?;
+        let current_ft = bin_interface.current_frame_time() as usize;
+        progress.set_frame_time(current_ft);
+        if let Some(target) = target {
+            match target_addr {
+                // Stops are not at every frame time so the first one
+                // in or after it is as close as it gets
+                None if current_ft >= target.frame_time => break true,
+                Some(_) if current_ft > target.frame_time => break false,
+                _ => {}
+            }
+        }
+        let thread = bin_interface.get_current_thread();
+        let rip = bin_interface
+            .get_register(GdbRegister::DREG_RIP, thread)
+            .to_usize();
+        if Some(rip) == target_addr {
+            let target = target.unwrap();
+            if current_ft == target.frame_time {
+                times_reached += 1;
+                if times_reached == target.instance_of_addr.unwrap_or(1) {
+                    break true;
+                }
+            }
+            // Step over the breakpoint before continuing
+            let step = GdbContAction {
+                type_: GdbActionType::ACTION_STEP,
+                target: thread,
+                signal_to_deliver: 0,
+            };
+            bin_interface.pin_mut().remove_sw_breakpoint(rip, 1);
+            let signal = bin_interface.pin_mut().continue_forward(step).unwrap();
+            bin_interface.pin_mut().set_sw_breakpoint(rip, 1);
+            if signal != 5 {
+                break false;
+            }
+        }
+        let signal = match manager.as_deref_mut() {
+            // The trampolines leave the replay where rr did not record it
+            Some(_) => bin_interface
+                .pin_mut()
+                .continue_forward_jog_undefined(cont)
+                .unwrap(),
+            None => bin_interface.pin_mut().continue_forward(cont).unwrap(),
+        };
+        if let Some(manager) = manager.as_deref_mut() {
+            manager
+                .clear_address_stack(bin_interface)
+                .map_err(|e| anyhow::anyhow!("Unable to read the trampoline stack: {}", e))?;
+        }
+        if signal != 5 {
+            // The program exited
+            break target.is_none();
+        }
+    };
+    if let Some(addr) = target_addr {
+        bin_interface.pin_mut().remove_sw_breakpoint(addr, 1);
+    }
+    Ok(reached)
+}
+
+fn bad_range(message: &str) -> ApiError {
+    ApiError::new(ApiErrorKind::BadRequest, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_structs::FileInfo;
+    use std::path::PathBuf;
+
+    fn function(name: &str, address: usize, size: usize) -> Function {
+        Function {
+            source_file: "main.c".into(),
+            demangled_name: name.into(),
+            address,
+            size,
+            start_line: 1,
+            end_line: 2,
+            return_addresses: Vec::new(),
+        }
+    }
+    fn erebor() -> Erebor {
+        let mut erebor = Erebor::default();
+        erebor.files.insert(
+            "main.c".into(),
+            FileInfo {
+                functions: vec![
+                    function("main", 0x1000, 0x40),
+                    function("helper", 0x1040, 0x10),
+                ],
+                lines: BTreeMap::from([
+                    (3, vec![0x1000]),
+                    (4, vec![0x1010, 0x1030]),
+                    (5, vec![0x1020]),
+                    (9, vec![0x1040]),
+                ]),
+            },
+        );
+        erebor
+    }
+    #[test]
+    fn functions() {
+        let erebor = erebor();
+        let addresses = [0x1048, 0x1000, 0x103f, 0x1048, 0x2000];
+        assert_eq!(called_functions(&erebor, &addresses), vec![0x1000, 0x1040]);
+        assert!(called_functions(&erebor, &[]).is_empty());
+    }
+    #[test]
+    fn heat() {
+        let erebor = erebor();
+        let main = erebor.function_at(0x1000).unwrap().clone();
+        // Blocks 0x1000-0x1008 and 0x1009-0x1018 ran twice, the one
+        // ending at 0x1024 never ran and 0x1025-0x1038 ran once
+        let executed = Executed {
+            instrumented: vec![0x1008, 0x1018, 0x1024, 0x1038, 0x1048],
+            addresses: vec![0x1008, 0x1018, 0x1008, 0x1018, 0x1038, 0x1048],
+        };
+        let map = heat_map(&erebor, &main, &executed);
+        assert_eq!(map.source_file, PathBuf::from("main.c"));
+        assert_eq!(map.addr_vs_times_executed.len(), 3);
+        assert_eq!(map.addr_vs_times_executed[&0x1018], 2);
+        // Line 5 has no recorded instruction of its own
+        assert_eq!(
+            map.line_vs_times_executed,
+            BTreeMap::from([(3, 4), (4, 3), (5, 1)])
+        );
+    }
+}